@@ -10,14 +10,32 @@ function createDeviceStore() {
         error: null,
     });
 
-    let pollInterval;
-    let pendingUpdate = false; // Block polling during updates
-    let debounceTimer; // Timer for debouncing forceLoad
+    let eventSource;
+
+    // Slider values sent but not echoed yet, by feature name.
+    // Echoes of earlier values from the same drag would make the slider jump back.
+    const pending = {};
+    const PENDING_TIMEOUT = 1000;
+
+    const setFeatureValue = (name, value) => update(s => ({
+        ...s,
+        features: s.features.map(f => f.name === name ? { ...f, value } : f)
+    }));
+
+    const isStaleEcho = (name, value) => {
+        const sent = pending[name];
+        if (!sent || !value || value.Slider === undefined) return false;
+        if (Math.abs(value.Slider - sent.value) < 0.05) {
+            clearTimeout(sent.timer);
+            delete pending[name];
+            return false;
+        }
+        // kept in case the server adjusted the last value
+        sent.echo = value;
+        return true;
+    };
 
     const load = async () => {
-        // Skip poll if we have a pending update (prevents stale data overwriting optimistic UI)
-        if (pendingUpdate) return;
-
         try {
             const url = import.meta.env.DEV ? 'http://localhost:3311/api/status' : '/api/status';
             const mixerUrl = import.meta.env.DEV ? 'http://localhost:3311/api/mixer/status' : '/api/mixer/status';
@@ -32,10 +50,7 @@ function createDeviceStore() {
             const data = await res.json();
             const mixerData = mixerRes && mixerRes.ok ? await mixerRes.json() : {};
 
-            // Double-check we're still not in a pending state
-            if (!pendingUpdate) {
-                update(s => ({ ...s, ...data, mixer: mixerData, loading: false, error: null }));
-            }
+            update(s => ({ ...s, ...data, mixer: mixerData, loading: false, error: null }));
         } catch (err) {
             console.error(err);
             update(s => ({
@@ -51,34 +66,64 @@ function createDeviceStore() {
         }
     };
 
-    // Force reload that bypasses the pendingUpdate guard
-    const forceLoad = async () => {
-        pendingUpdate = false;
-        await load();
-    };
-
-    // Debounced forceLoad to avoid interrupting fast slider dragging
-    const debouncedForceLoad = () => {
-        if (debounceTimer) clearTimeout(debounceTimer);
-        debounceTimer = setTimeout(forceLoad, 500); // Wait 500ms after last interaction
+    // Applies a server-sent event, see src/events.rs
+    const handleEvent = (event) => {
+        switch (event.type) {
+            case 'feature':
+                if (isStaleEcho(event.name, event.value)) break;
+                setFeatureValue(event.name, event.value);
+                break;
+            case 'eq':
+                update(s => ({ ...s, auto_preamp: event.auto_preamp, loudness: event.loudness, tone: event.tone }));
+                break;
+            case 'mixer':
+                update(s => {
+                    // only the levels that are set changed
+                    const levels = Object.fromEntries(Object.entries(event.levels).filter(([, v]) => v !== null));
+                    return { ...s, mixer: { ...s.mixer, [event.name]: { ...s.mixer[event.name], ...levels } } };
+                });
+                break;
+            case 'profile':
+                update(s => ({ ...s, active_layers: event.active_layers }));
+                break;
+            case 'profile_file':
+                update(s => {
+                    const profile_errors = { ...s.profile_errors };
+                    if (event.error) profile_errors[event.name] = event.error;
+                    else delete profile_errors[event.name];
+                    return { ...s, profile_errors };
+                });
+                break;
+            case 'device':
+                update(s => ({ ...s, error: event.connected ? null : 'Device disconnected' }));
+                break;
+            case 'resync':
+                load();
+                break;
+        }
     };
 
     return {
         subscribe,
         load,
-        startPolling: (interval = 2000) => {
-            if (pollInterval) clearInterval(pollInterval);
-            load(); // Initial load
-            pollInterval = setInterval(load, interval);
+        // Loads the full state, then follows the changes pushed by the server
+        connect: () => {
+            if (eventSource) eventSource.close();
+            const url = import.meta.env.DEV ? 'http://localhost:3311/api/events' : '/api/events';
+            eventSource = new EventSource(url);
+            // also after reconnects, changes may have been missed meanwhile
+            eventSource.onopen = () => load();
+            eventSource.onmessage = (msg) => handleEvent(JSON.parse(msg.data));
+            eventSource.onerror = () => {
+                update(s => ({ ...s, error: 'Connection Error' }));
+            };
         },
-        stopPolling: () => {
-            if (pollInterval) clearInterval(pollInterval);
+        disconnect: () => {
+            if (eventSource) eventSource.close();
+            eventSource = null;
         },
         updateFeature: async (name, value) => {
-            // Block polling from overwriting our optimistic update
-            pendingUpdate = true;
-
-            // Optimistic UI update
+            // Optimistic UI update, the server pushes the actual values
             update(state => {
                 const newFeatures = state.features.map(f => {
                     let newValue = f.value;
@@ -103,6 +148,17 @@ function createDeviceStore() {
                 return { ...state, features: newFeatures };
             });
 
+            if (typeof value === 'number') {
+                clearTimeout(pending[name]?.timer);
+                // the server may have adjusted the value, take its word after a while
+                const timer = setTimeout(() => {
+                    const { echo } = pending[name];
+                    delete pending[name];
+                    if (echo) setFeatureValue(name, echo);
+                }, PENDING_TIMEOUT);
+                pending[name] = { value, timer };
+            }
+
             try {
                 const payload = {};
                 payload.name = name;
@@ -115,17 +171,25 @@ function createDeviceStore() {
                     headers: { 'Content-Type': 'application/json' },
                     body: JSON.stringify(payload)
                 });
-
-                // Re-fetch authoritative state after server has processed (debounced)
-                debouncedForceLoad();
             } catch (err) {
                 console.error('Failed to update feature', err);
-                pendingUpdate = false;
+                load();
+            }
+        },
+        applyProfile: async (name, layers) => {
+            try {
+                const url = import.meta.env.DEV ? 'http://localhost:3311/api/profile/apply' : '/api/profile/apply';
+                const res = await fetch(url, {
+                    method: 'POST',
+                    headers: { 'Content-Type': 'application/json' },
+                    body: JSON.stringify({ name, layers })
+                });
+                if (!res.ok) console.error('Failed to apply profile', await res.text());
+            } catch (err) {
+                console.error('Failed to apply profile', err);
             }
         },
         updateMixer: async (name, payload) => {
-            pendingUpdate = true;
-
             update(state => {
                 const newMixer = { ...state.mixer };
                 if (!newMixer[name]) newMixer[name] = {};
@@ -140,12 +204,9 @@ function createDeviceStore() {
                     headers: { 'Content-Type': 'application/json' },
                     body: JSON.stringify({ name, ...payload })
                 });
-
-                // Re-fetch after server processed (debounced)
-                debouncedForceLoad();
             } catch (err) {
                 console.error('Failed to update mixer', err);
-                pendingUpdate = false;
+                load();
             }
         }
     };
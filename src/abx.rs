@@ -0,0 +1,342 @@
+//! Blind ABX comparison of the EQ of two profiles.
+//! X is A or B at random, drawn anew for every trial. Which one it is
+//! only comes out once all trials are guessed or the session is stopped.
+//! Both are level-matched via the Pre-Amp, so loudness gives nothing away.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::hash::{BuildHasher, RandomState};
+use std::io::ErrorKind;
+use std::time::SystemTime;
+use utoipa::ToSchema;
+
+use crate::Feature;
+use crate::eq::{self, MAX_FREQ, MAX_GAIN, MIN_FREQ};
+use crate::fit::FIT_POINTS;
+use crate::profile::{Layer, Profile, Snapshot};
+
+/// Trials of a session if none are given
+pub const DEFAULT_TRIALS: usize = 16;
+pub const MAX_TRIALS: usize = 100;
+
+/// A difference counts as heard below this p-value
+pub const SIGNIFICANCE: f64 = 0.05;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Choice {
+    A,
+    B,
+    X,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, ToSchema)]
+pub struct Trial {
+    /// What X was, `A` or `B`
+    pub x: Choice,
+    pub guess: Choice,
+}
+
+/// The session as the listener may see it
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct AbxStatus {
+    pub a: String,
+    pub b: String,
+    /// Pre-Amps A and B are played with, only once all trials are done,
+    /// they would tell which one is playing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preamps: Option<[f32; 2]>,
+    pub trials: usize,
+    pub completed: usize,
+    /// What's playing right now, `None` before the first switch
+    pub selected: Option<Choice>,
+    /// Only once all trials are done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<AbxResult>,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct AbxResult {
+    pub correct: usize,
+    pub total: usize,
+    /// Chance of at least `correct` right guesses by guessing at random
+    pub p_value: f64,
+    /// `p_value` is below [`SIGNIFICANCE`]
+    pub significant: bool,
+    pub guesses: Vec<Trial>,
+}
+
+pub struct AbxSession {
+    pub a: String,
+    pub b: String,
+    /// EQ layers of A and B, level-matched
+    profiles: [Profile; 2],
+    preamps: [f32; 2],
+    trials: usize,
+    /// A or B, never X
+    x: Choice,
+    pub selected: Option<Choice>,
+    guesses: Vec<Trial>,
+    /// The EQ from before the session, restored when it's stopped
+    pub snapshot: Snapshot,
+}
+
+fn invalid_input(message: String) -> Box<dyn Error> {
+    Box::new(std::io::Error::new(ErrorKind::InvalidInput, message))
+}
+
+/// A or B with equal chance.
+/// The std hasher is seeded randomly, that's plenty for picking X.
+fn random_side() -> Choice {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    if RandomState::new().hash_one(nanos) & 1 == 0 {
+        Choice::A
+    } else {
+        Choice::B
+    }
+}
+
+/// Average level in dB of Pre-Amp and bands, over log-spaced frequencies
+pub fn level(bands: &[f32; 11]) -> f64 {
+    let points = eq::log_spaced(FIT_POINTS, MIN_FREQ, MAX_FREQ);
+    let sum: f64 = points
+        .iter()
+        .map(|freq| eq::bands_response(&bands[1..], *freq))
+        .sum();
+    bands[0] as f64 + sum / points.len() as f64
+}
+
+/// Lowers the Pre-Amp of the louder one to the level of the other.
+/// Nothing is raised, so neither clips more than it would on its own.
+pub fn level_match(a: &[f32; 11], b: &[f32; 11]) -> [f32; 2] {
+    let levels = [level(a), level(b)];
+    let target = levels[0].min(levels[1]);
+    let matched = |bands: &[f32; 11], level: f64| {
+        let preamp = bands[0] as f64 - (level - target);
+        ((preamp * 10.0).round() / 10.0).max(-MAX_GAIN as f64) as f32
+    };
+    [matched(a, levels[0]), matched(b, levels[1])]
+}
+
+/// One-sided binomial test: chance of `correct` or more out of `total`
+/// when every guess is right with a chance of 1/2
+pub fn p_value(correct: usize, total: usize) -> f64 {
+    if correct == 0 {
+        return 1.0;
+    }
+    // C(total, k) / 2^total, computed step by step to stay in range
+    let mut term = 0.5f64.powi(total as i32);
+    let mut sum = 0.0;
+    for k in 0..=total {
+        if k >= correct {
+            sum += term;
+        }
+        term *= (total - k) as f64 / (k + 1) as f64;
+    }
+    sum.min(1.0)
+}
+
+impl AbxSession {
+    /// Only the EQ layers of the profiles are used,
+    /// the effects would tell A and B apart
+    pub fn new(
+        names: [String; 2],
+        profiles: [Profile; 2],
+        trials: usize,
+        snapshot: Snapshot,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !(1..=MAX_TRIALS).contains(&trials) {
+            return Err(invalid_input(format!(
+                "Trials must be within 1 and {}",
+                MAX_TRIALS
+            )));
+        }
+
+        let mut bands = [[0.0; 11]; 2];
+        for ((bands, profile), name) in
+            bands.iter_mut().zip(&profiles).zip(&names)
+        {
+            *bands = profile.ten_band_eq().ok_or_else(|| {
+                invalid_input(format!("Profile {} has no EQ layer", name))
+            })?;
+        }
+        let preamps = level_match(&bands[0], &bands[1]);
+        let profiles = [0, 1].map(|idx| {
+            let mut matched = bands[idx];
+            matched[0] = preamps[idx];
+            Profile::from_ten_band_eq(matched)
+        });
+
+        let [a, b] = names;
+        Ok(Self {
+            a,
+            b,
+            profiles,
+            preamps,
+            trials,
+            x: random_side(),
+            selected: None,
+            guesses: Vec::new(),
+            snapshot,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.guesses.len() >= self.trials
+    }
+
+    /// EQ layer to apply for a choice, X resolved
+    pub fn profile(&self, choice: Choice) -> &Profile {
+        let side = if choice == Choice::X { self.x } else { choice };
+        match side {
+            Choice::B => &self.profiles[1],
+            _ => &self.profiles[0],
+        }
+    }
+
+    /// Records a guess for X and draws X for the next trial.
+    /// Nothing is selected then, switching the device right away would
+    /// give away whether X changed.
+    pub fn guess(&mut self, guess: Choice) -> Result<(), Box<dyn Error>> {
+        if guess == Choice::X {
+            return Err(invalid_input("Guess A or B".to_string()));
+        }
+        if self.is_finished() {
+            return Err(invalid_input("All trials are done".to_string()));
+        }
+        self.guesses.push(Trial { x: self.x, guess });
+        self.x = random_side();
+        self.selected = None;
+        Ok(())
+    }
+
+    /// Score so far, reveals what X was
+    pub fn result(&self) -> AbxResult {
+        let correct = self.guesses.iter().filter(|t| t.x == t.guess).count();
+        let p_value = p_value(correct, self.guesses.len());
+        AbxResult {
+            correct,
+            total: self.guesses.len(),
+            p_value,
+            significant: p_value < SIGNIFICANCE,
+            guesses: self.guesses.clone(),
+        }
+    }
+
+    pub fn status(&self) -> AbxStatus {
+        AbxStatus {
+            a: self.a.clone(),
+            b: self.b.clone(),
+            preamps: self.is_finished().then_some(self.preamps),
+            trials: self.trials,
+            completed: self.guesses.len(),
+            selected: self.selected,
+            result: self.is_finished().then(|| self.result()),
+        }
+    }
+
+    /// The status once the session is stopped, with everything revealed
+    pub fn final_status(&self) -> AbxStatus {
+        AbxStatus {
+            preamps: Some(self.preamps),
+            result: Some(self.result()),
+            ..self.status()
+        }
+    }
+
+    /// Puts the EQ from before the session in place of what's playing,
+    /// for everything the listener sees while it runs
+    pub fn mask(&self, features: &mut [Feature]) {
+        let Some(before) = self.snapshot.profile.layer_targets(Layer::Eq)
+        else {
+            return;
+        };
+        for feature in features {
+            if let Some(target) = before.iter().find(|f| f.name == feature.name)
+            {
+                feature.value = target.value.clone();
+            }
+        }
+    }
+
+    /// Pre-Amp and bands from before the session, see [`AbxSession::mask`]
+    pub fn masked_ten_band_eq(&self) -> [f32; 11] {
+        self.snapshot.profile.ten_band_eq().unwrap_or([0.0; 11])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn session(trials: usize) -> AbxSession {
+        let mut loud = [0.0; 11];
+        loud[1..].fill(6.0);
+        let snapshot = Snapshot {
+            profile: Profile::from_ten_band_eq([1.0; 11]),
+            active_layers: BTreeMap::new(),
+        };
+        AbxSession::new(
+            ["flat".to_string(), "loud".to_string()],
+            [
+                Profile::from_ten_band_eq([0.0; 11]),
+                Profile::from_ten_band_eq(loud),
+            ],
+            trials,
+            snapshot,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn p_value_of_binomial_test() {
+        assert!((p_value(12, 16) - 0.0384).abs() < 1e-4);
+        assert_eq!(p_value(0, 16), 1.0);
+        assert!((p_value(16, 16) - 0.5f64.powi(16)).abs() < 1e-12);
+        assert!((p_value(1, 1) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn louder_side_is_lowered() {
+        let flat = [0.0; 11];
+        let mut loud = [0.0; 11];
+        loud[1..].fill(6.0);
+        let preamps = level_match(&flat, &loud);
+        assert_eq!(preamps[0], 0.0);
+        assert!(preamps[1] < 0.0);
+        let mut matched = loud;
+        matched[0] = preamps[1];
+        assert!((level(&matched) - level(&flat)).abs() < 0.1);
+        assert_eq!(level_match(&loud, &loud), [0.0; 2]);
+    }
+
+    #[test]
+    fn status_stays_blind_until_done() {
+        let mut session = session(2);
+        let status = session.status();
+        assert!(status.preamps.is_none() && status.result.is_none());
+
+        session.guess(Choice::A).unwrap();
+        assert!(session.status().preamps.is_none());
+        session.guess(Choice::B).unwrap();
+        assert!(session.status().preamps.is_some());
+        assert_eq!(session.status().result.unwrap().total, 2);
+        assert!(session.guess(Choice::A).is_err());
+    }
+
+    #[test]
+    fn mask_shows_the_eq_from_before() {
+        let session = session(16);
+        let mut features =
+            session.profile(Choice::X).layer_targets(Layer::Eq).unwrap();
+        session.mask(&mut features);
+        let sliders: Vec<f32> =
+            features.iter().filter_map(|f| f.value.as_f32()).collect();
+        assert_eq!(sliders, vec![1.0; 11]);
+        assert_eq!(session.masked_ten_band_eq(), [1.0; 11]);
+    }
+}
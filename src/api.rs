@@ -1,20 +1,43 @@
 use axum::{
-    extract::{State, Json},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Query, State, Json},
+    http::{header, StatusCode},
+    response::{IntoResponse, Sse, sse},
 };
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use utoipa::{IntoParams, ToSchema};
 use crate::BlasterXG6;
+use crate::abx::{self, AbxSession, Choice};
+use crate::apo;
+use crate::autoeq;
+use crate::eq;
+use crate::events::{Event, Events};
+use crate::export;
+use crate::fit;
+use crate::config::{self, Config, ConfigUpdate};
+use crate::autoswitch::{self, AppRule, AutoSwitch};
+use crate::history::History;
+use crate::loudness::LoudnessUpdate;
+use crate::persist::ProfileSaver;
+use crate::preamp::AutoPreAmpUpdate;
+use crate::presets;
+use crate::schedule::{self, Schedule, Scheduler};
+use crate::profile::{ActiveLayer, Layer, Profile, Snapshot};
+use crate::ramp::{self, Ramp};
+use crate::share;
+use crate::target;
+use crate::tone::{self, ToneControls};
 
+// the output gets parsed, so it must not be translated
 fn run_sys_cmd(cmd: &str, args: &[&str]) -> Option<std::process::Output> {
     if std::path::Path::new("/.flatpak-info").exists() {
-        let mut spawn_args = vec!["--host", cmd];
+        let mut spawn_args = vec!["--host", "--env=LC_ALL=C", cmd];
         spawn_args.extend_from_slice(args);
         std::process::Command::new("flatpak-spawn").args(&spawn_args).output().ok()
     } else {
-        std::process::Command::new(cmd).args(args).output().ok()
+        std::process::Command::new(cmd).env("LC_ALL", "C").args(args).output().ok()
     }
 }
 
@@ -22,9 +45,22 @@ fn run_sys_cmd(cmd: &str, args: &[&str]) -> Option<std::process::Output> {
 // Shared state
 pub struct AppState {
     pub device: Mutex<BlasterXG6>,
+    pub saver: ProfileSaver,
+    pub autoswitch: Mutex<AutoSwitch>,
+    pub scheduler: Mutex<Scheduler>,
+    /// Lock after `device`, never before
+    pub history: Mutex<History>,
+    /// Profiles that failed to load, with the reason
+    pub profile_errors: Mutex<std::collections::BTreeMap<String, String>>,
+    /// EQ ramp of the profile switch in flight
+    pub ramp: Ramp,
+    /// Lock after `device`, never before
+    pub abx: Mutex<Option<AbxSession>>,
+    /// Same channel as `device.events`, without locking the device
+    pub events: Events,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, ToSchema)]
 pub struct MixerResponse {
     pub playback_vol: Option<f32>,
     pub playback_vol_l: Option<f32>,
@@ -37,19 +73,91 @@ pub struct MixerResponse {
 }
 
 fn get_pulse_device(prefix: &str, is_sink: bool) -> Option<String> {
+    find_pulse_device(prefix, is_sink).map(|(_, name)| name)
+}
+
+/// Index and name of the first matching sink / source
+fn find_pulse_device(prefix: &str, is_sink: bool) -> Option<(String, String)> {
     let mode = if is_sink { "sinks" } else { "sources" };
     let out = run_sys_cmd("pactl", &["list", "short", mode])?;
     let s = String::from_utf8_lossy(&out.stdout);
     for line in s.lines() {
         if line.contains(prefix) && (is_sink || !line.contains(".monitor")) {
-            if let Some(name) = line.split_whitespace().nth(1) {
-                return Some(name.to_string());
+            let mut columns = line.split_whitespace();
+            if let (Some(index), Some(name)) = (columns.next(), columns.next()) {
+                return Some((index.to_string(), name.to_string()));
             }
         }
     }
     None
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamDirection {
+    Playback,
+    Capture,
+}
+
+/// An application stream playing to or recording from the G6
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct PulseStream {
+    pub direction: StreamDirection,
+    pub application_name: Option<String>,
+    pub binary: Option<String>,
+}
+
+/// All playback streams on the G6 sink and capture streams on its source
+pub fn list_g6_streams() -> Vec<PulseStream> {
+    let mut streams = Vec::new();
+
+    let targets = [
+        (StreamDirection::Playback, "sink-inputs", "Sink:", true),
+        (StreamDirection::Capture, "source-outputs", "Source:", false),
+    ];
+    for (direction, kind, device_key, is_sink) in targets {
+        let Some((index, _)) = find_pulse_device(&config::get().pulse_prefix, is_sink) else {
+            continue;
+        };
+        let Some(out) = run_sys_cmd("pactl", &["list", kind]) else {
+            continue;
+        };
+        let s = String::from_utf8_lossy(&out.stdout);
+
+        // blocks start with an unindented "Sink Input #12" / "Source Output #3"
+        let mut blocks: Vec<Vec<&str>> = Vec::new();
+        for line in s.lines() {
+            if !line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+                blocks.push(Vec::new());
+            } else if let Some(block) = blocks.last_mut() {
+                block.push(line.trim());
+            }
+        }
+
+        for block in blocks {
+            let on_g6 = block.iter().any(|line| {
+                line.strip_prefix(device_key).map(str::trim) == Some(index.as_str())
+            });
+            if !on_g6 {
+                continue;
+            }
+            let property = |key: &str| {
+                block.iter().find_map(|line| {
+                    let (k, v) = line.split_once(" = ")?;
+                    (k.trim() == key).then(|| v.trim().trim_matches('"').to_string())
+                })
+            };
+            streams.push(PulseStream {
+                direction,
+                application_name: property("application.name"),
+                binary: property("application.process.binary"),
+            });
+        }
+    }
+
+    streams
+}
+
 fn get_pulse_vols(name: &str, is_source: bool) -> (Option<f32>, Option<f32>, Option<f32>) {
     let cmd = if is_source { "get-source-volume" } else { "get-sink-volume" };
     let out = run_sys_cmd("pactl", &[cmd, name]);
@@ -104,85 +212,128 @@ fn get_pulse_mute(name: &str, is_source: bool) -> Option<bool> {
     if s.contains("yes") { Some(true) } else if s.contains("no") { Some(false) } else { None }
 }
 
-pub async fn get_mixer() -> impl IntoResponse {
-    let mut map = std::collections::HashMap::new();
-    let controls = ["Speaker", "Line In", "External Mic", "S/PDIF In", "What U Hear"];
-    
-    let g6_sink = get_pulse_device("Sound_BlasterX_G6", true);
-    let g6_source = get_pulse_device("Sound_BlasterX_G6", false);
+/// Levels of one control from the output of `amixer sget`
+fn parse_amixer(s: &str) -> MixerResponse {
+    let mut p_mute = None;
+    let mut c_mute = None;
 
-    for &ctrl in &controls {
-        let output = run_sys_cmd("amixer", &["-c", "G6", "sget", ctrl]);
-        if let Some(out) = output {
-            let s = String::from_utf8_lossy(&out.stdout);
-            let mut p_vol = None;
-            let mut p_vol_l = None;
-            let mut p_vol_r = None;
-            let mut c_vol = None;
-            let mut c_vol_l = None;
-            let mut c_vol_r = None;
-            let mut p_mute = None;
-            let mut c_mute = None;
-
-            let mut parsed_p_l = None;
-            let mut parsed_p_r = None;
-            let mut parsed_c_l = None;
-            let mut parsed_c_r = None;
-
-            for line in s.lines() {
-                if line.contains("Front Left:") || (line.contains("Mono:") && !s.contains("Front Left:")) {
-                    let parts: Vec<&str> = line.split("Capture").collect();
-                    if let Some(p) = parts.get(0) {
-                        if let Some(start) = p.find('[') {
-                            if let Some(end) = p[start..].find('%') {
-                                parsed_p_l = p[start+1..start+end].parse::<f32>().ok().map(|v| v / 100.0);
-                            }
-                        }
-                        if p.contains("[off]") { p_mute = Some(true); } else if p.contains("[on]") { p_mute = Some(false); }
+    let mut parsed_p_l = None;
+    let mut parsed_p_r = None;
+    let mut parsed_c_l = None;
+    let mut parsed_c_r = None;
+
+    for line in s.lines() {
+        if line.contains("Front Left:") || (line.contains("Mono:") && !s.contains("Front Left:")) {
+            let parts: Vec<&str> = line.split("Capture").collect();
+            if let Some(p) = parts.get(0) {
+                if let Some(start) = p.find('[') {
+                    if let Some(end) = p[start..].find('%') {
+                        parsed_p_l = p[start+1..start+end].parse::<f32>().ok().map(|v| v / 100.0);
                     }
-                    if let Some(p) = parts.get(1) {
-                        if let Some(start) = p.find('[') {
-                            if let Some(end) = p[start..].find('%') {
-                                parsed_c_l = p[start+1..start+end].parse::<f32>().ok().map(|v| v / 100.0);
-                            }
-                        }
-                        if p.contains("[off]") { c_mute = Some(true); } else if p.contains("[on]") { c_mute = Some(false); }
+                }
+                if p.contains("[off]") { p_mute = Some(true); } else if p.contains("[on]") { p_mute = Some(false); }
+            }
+            if let Some(p) = parts.get(1) {
+                if let Some(start) = p.find('[') {
+                    if let Some(end) = p[start..].find('%') {
+                        parsed_c_l = p[start+1..start+end].parse::<f32>().ok().map(|v| v / 100.0);
                     }
                 }
-                if line.contains("Front Right:") {
-                    let parts: Vec<&str> = line.split("Capture").collect();
-                    if let Some(p) = parts.get(0) {
-                        if let Some(start) = p.find('[') {
-                            if let Some(end) = p[start..].find('%') {
-                                parsed_p_r = p[start+1..start+end].parse::<f32>().ok().map(|v| v / 100.0);
-                            }
-                        }
+                if p.contains("[off]") { c_mute = Some(true); } else if p.contains("[on]") { c_mute = Some(false); }
+            }
+        }
+        if line.contains("Front Right:") {
+            let parts: Vec<&str> = line.split("Capture").collect();
+            if let Some(p) = parts.get(0) {
+                if let Some(start) = p.find('[') {
+                    if let Some(end) = p[start..].find('%') {
+                        parsed_p_r = p[start+1..start+end].parse::<f32>().ok().map(|v| v / 100.0);
                     }
-                    if let Some(p) = parts.get(1) {
-                        if let Some(start) = p.find('[') {
-                            if let Some(end) = p[start..].find('%') {
-                                parsed_c_r = p[start+1..start+end].parse::<f32>().ok().map(|v| v / 100.0);
-                            }
-                        }
+                }
+            }
+            if let Some(p) = parts.get(1) {
+                if let Some(start) = p.find('[') {
+                    if let Some(end) = p[start..].find('%') {
+                        parsed_c_r = p[start+1..start+end].parse::<f32>().ok().map(|v| v / 100.0);
                     }
                 }
             }
-            
-            p_vol_l = parsed_p_l;
-            p_vol_r = parsed_p_r.or(parsed_p_l);
-            p_vol = match (p_vol_l, p_vol_r) {
-                (Some(l), Some(r)) => Some((l + r) / 2.0),
-                (Some(l), None) => Some(l),
-                _ => None,
-            };
-            
-            c_vol_l = parsed_c_l;
-            c_vol_r = parsed_c_r.or(parsed_c_l);
-            c_vol = match (c_vol_l, c_vol_r) {
-                (Some(l), Some(r)) => Some((l + r) / 2.0),
-                (Some(l), None) => Some(l),
-                _ => None,
-            };
+        }
+    }
+    
+    let p_vol_l = parsed_p_l;
+    let p_vol_r = parsed_p_r.or(parsed_p_l);
+    let p_vol = match (p_vol_l, p_vol_r) {
+        (Some(l), Some(r)) => Some((l + r) / 2.0),
+        (Some(l), None) => Some(l),
+        _ => None,
+    };
+    
+    let c_vol_l = parsed_c_l;
+    let c_vol_r = parsed_c_r.or(parsed_c_l);
+    let c_vol = match (c_vol_l, c_vol_r) {
+        (Some(l), Some(r)) => Some((l + r) / 2.0),
+        (Some(l), None) => Some(l),
+        _ => None,
+    };
+
+    MixerResponse {
+        playback_vol: p_vol,
+        playback_vol_l: p_vol_l,
+        playback_vol_r: p_vol_r,
+        playback_mute: p_mute,
+        capture_vol: c_vol,
+        capture_vol_l: c_vol_l,
+        capture_vol_r: c_vol_r,
+        capture_mute: c_mute,
+    }
+}
+
+/// Playback volume of the Speaker control alone, the one
+/// [`read_mixer`] reports, without querying the other controls
+pub fn read_speaker_volume() -> Option<f32> {
+    let config = config::get();
+    if let Some(sink) = get_pulse_device(&config.pulse_prefix, true)
+        && let (Some(avg), _, _) = get_pulse_vols(&sink, false)
+    {
+        return Some(avg);
+    }
+
+    let out = run_sys_cmd("amixer", &["-c", &config.alsa_card, "sget", "Speaker"])?;
+    parse_amixer(&String::from_utf8_lossy(&out.stdout)).playback_vol
+}
+
+#[utoipa::path(
+    get, path = "/api/mixer/status", tag = "mixer",
+    responses((status = 200, body = std::collections::HashMap<String, MixerResponse>))
+)]
+pub async fn get_mixer() -> impl IntoResponse {
+    Json(read_mixer())
+}
+
+/// Reads volume and mute state of every G6 mixer control,
+/// preferring the PulseAudio/PipeWire view where one exists
+pub fn read_mixer() -> std::collections::HashMap<String, MixerResponse> {
+    let mut map = std::collections::HashMap::new();
+    let controls = ["Speaker", "Line In", "External Mic", "S/PDIF In", "What U Hear"];
+    
+    let config = config::get();
+    let g6_sink = get_pulse_device(&config.pulse_prefix, true);
+    let g6_source = get_pulse_device(&config.pulse_prefix, false);
+
+    for &ctrl in &controls {
+        let output = run_sys_cmd("amixer", &["-c", &config.alsa_card, "sget", ctrl]);
+        if let Some(out) = output {
+            let MixerResponse {
+                playback_vol: mut p_vol,
+                playback_vol_l: mut p_vol_l,
+                playback_vol_r: mut p_vol_r,
+                playback_mute: mut p_mute,
+                capture_vol: mut c_vol,
+                capture_vol_l: mut c_vol_l,
+                capture_vol_r: mut c_vol_r,
+                capture_mute: mut c_mute,
+            } = parse_amixer(&String::from_utf8_lossy(&out.stdout));
 
             // Sync with Pulse OS layer if available
             if ctrl == "Speaker" {
@@ -215,10 +366,10 @@ pub async fn get_mixer() -> impl IntoResponse {
             });
         }
     }
-    Json(map)
+    map
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Debug, Default, ToSchema)]
 pub struct MixerSetRequest {
     pub name: String,
     pub playback_vol: Option<f32>,
@@ -231,9 +382,59 @@ pub struct MixerSetRequest {
     pub capture_mute: Option<bool>,
 }
 
-pub async fn set_mixer(Json(payload): Json<MixerSetRequest>) -> impl IntoResponse {
-    let g6_sink = get_pulse_device("Sound_BlasterX_G6", true);
-    let g6_source = get_pulse_device("Sound_BlasterX_G6", false);
+impl MixerSetRequest {
+    /// Builds a request that restores previously read levels of a control
+    pub fn from_levels(name: &str, levels: &MixerResponse) -> Self {
+        Self {
+            name: name.to_string(),
+            playback_vol: levels.playback_vol,
+            playback_vol_l: levels.playback_vol_l,
+            playback_vol_r: levels.playback_vol_r,
+            playback_mute: levels.playback_mute,
+            capture_vol: levels.capture_vol,
+            capture_vol_l: levels.capture_vol_l,
+            capture_vol_r: levels.capture_vol_r,
+            capture_mute: levels.capture_mute,
+        }
+    }
+
+    /// The levels this request sets, `None` where it leaves them alone
+    pub fn levels(&self) -> MixerResponse {
+        MixerResponse {
+            playback_vol: self.playback_vol,
+            playback_vol_l: self.playback_vol_l,
+            playback_vol_r: self.playback_vol_r,
+            playback_mute: self.playback_mute,
+            capture_vol: self.capture_vol,
+            capture_vol_l: self.capture_vol_l,
+            capture_vol_r: self.capture_vol_r,
+            capture_mute: self.capture_mute,
+        }
+    }
+}
+
+#[utoipa::path(
+    post, path = "/api/mixer/feature", tag = "mixer", request_body = MixerSetRequest,
+    responses((status = 200))
+)]
+pub async fn set_mixer(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<MixerSetRequest>,
+) -> impl IntoResponse {
+    write_mixer(&payload);
+    state.events.publish(Event::Mixer {
+        name: payload.name.clone(),
+        levels: payload.levels(),
+    });
+    StatusCode::OK.into_response()
+}
+
+/// Applies the volumes and mutes of a request,
+/// through PulseAudio/PipeWire for Speaker and External Mic, ALSA otherwise
+pub fn write_mixer(payload: &MixerSetRequest) {
+    let config = config::get();
+    let g6_sink = get_pulse_device(&config.pulse_prefix, true);
+    let g6_source = get_pulse_device(&config.pulse_prefix, false);
 
     // Playback volumes
     let has_p_l = payload.playback_vol_l.is_some();
@@ -253,9 +454,9 @@ pub async fn set_mixer(Json(payload): Json<MixerSetRequest>) -> impl IntoRespons
         } else {
             let pct = format!("{},{}", pct_l, pct_r); // amixer uses 40%,50% format
             let args = if payload.name == "Speaker" || payload.name == "What U Hear" {
-                vec!["-c", "G6", "sset", &payload.name, "0", &pct]
+                vec!["-c", &config.alsa_card, "sset", &payload.name, "0", &pct]
             } else {
-                vec!["-c", "G6", "sset", &payload.name, "0", &pct, "playback"]
+                vec!["-c", &config.alsa_card, "sset", &payload.name, "0", &pct, "playback"]
             };
             let _ = run_sys_cmd("amixer", &args[..]);
         }
@@ -278,9 +479,9 @@ pub async fn set_mixer(Json(payload): Json<MixerSetRequest>) -> impl IntoRespons
         } else {
             let pct = format!("{},{}", pct_l, pct_r);
             let args = if payload.name == "Speaker" || payload.name == "What U Hear" {
-                vec!["-c", "G6", "sset", &payload.name, "0", &pct]
+                vec!["-c", &config.alsa_card, "sset", &payload.name, "0", &pct]
             } else {
-                vec!["-c", "G6", "sset", &payload.name, "0", &pct, "capture"]
+                vec!["-c", &config.alsa_card, "sset", &payload.name, "0", &pct, "capture"]
             };
             let _ = run_sys_cmd("amixer", &args[..]);
         }
@@ -292,9 +493,9 @@ pub async fn set_mixer(Json(payload): Json<MixerSetRequest>) -> impl IntoRespons
         } else {
             let a_state = if m { "mute" } else { "unmute" };
             let args = if payload.name == "Speaker" || payload.name == "What U Hear" {
-                vec!["-c", "G6", "sset", &payload.name, "0", a_state]
+                vec!["-c", &config.alsa_card, "sset", &payload.name, "0", a_state]
             } else {
-                vec!["-c", "G6", "sset", &payload.name, "0", a_state, "playback"]
+                vec!["-c", &config.alsa_card, "sset", &payload.name, "0", a_state, "playback"]
             };
             let _ = run_sys_cmd("amixer", &args[..]);
         }
@@ -306,71 +507,1414 @@ pub async fn set_mixer(Json(payload): Json<MixerSetRequest>) -> impl IntoRespons
         } else {
             let a_state = if m { "mute" } else { "unmute" };
             let args = if payload.name == "Speaker" || payload.name == "What U Hear" {
-                vec!["-c", "G6", "sset", &payload.name, "0", a_state]
+                vec!["-c", &config.alsa_card, "sset", &payload.name, "0", a_state]
             } else {
-                vec!["-c", "G6", "sset", &payload.name, "0", a_state, "capture"]
+                vec!["-c", &config.alsa_card, "sset", &payload.name, "0", a_state, "capture"]
             };
             let _ = run_sys_cmd("amixer", &args[..]);
         }
     }
-    StatusCode::OK.into_response()
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct StatusResponse {
     pub features: Vec<crate::Feature>,
     pub eq_bands: Option<[f32; 11]>,
+    pub active_layers: std::collections::BTreeMap<Layer, ActiveLayer>,
+    /// Profiles that failed to load, with the reason
+    pub profile_errors: std::collections::BTreeMap<String, String>,
 }
 
+#[utoipa::path(
+    get, path = "/api/status", tag = "device",
+    responses((status = 200, body = StatusResponse))
+)]
 pub async fn get_status(
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
     let device = state.device.lock().await;
 
     // Clone features to return
-    let features = device.features.clone();
-    let eq_bands = device.get_ten_band_eq();
+    let mut features = device.features.clone();
+    let mut eq_bands = device.get_ten_band_eq();
+    if let Some(session) = state.abx.lock().await.as_ref() {
+        session.mask(&mut features);
+        eq_bands = Some(session.masked_ten_band_eq());
+    }
 
     Json(StatusResponse {
         features,
         eq_bands,
+        active_layers: device.active_layers.clone(),
+        profile_errors: state.profile_errors.lock().await.clone(),
     })
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct SetFeatureRequest {
     pub name: String,
     pub toggle: Option<bool>,
     pub slider: Option<f32>,
 }
 
+#[utoipa::path(
+    post, path = "/api/feature", tag = "device", request_body = SetFeatureRequest,
+    responses((status = 200), (status = 500, body = String))
+)]
 pub async fn set_feature(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<SetFeatureRequest>,
 ) -> impl IntoResponse {
     let mut device = state.device.lock().await;
+    let before = device.features.clone();
+
+    if let Err(e) = apply_feature_request(&mut device, &payload) {
+        return e.into_response();
+    }
+
+    state.history.lock().await.record(&payload.name, before, device.features.clone());
+
+    // written to default.json by the background saver once things settle
+    state.saver.request();
+
+    // Success
+    StatusCode::OK.into_response()
+}
+
+/// Sets several features at once, e.g. all EQ bands of a preset.
+/// Counts as a single step in the undo history.
+#[utoipa::path(
+    post, path = "/api/features", tag = "device", request_body = Vec<SetFeatureRequest>,
+    responses((status = 200), (status = 500, body = String))
+)]
+pub async fn set_features(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<Vec<SetFeatureRequest>>,
+) -> impl IntoResponse {
+    let mut device = state.device.lock().await;
+    let before = device.features.clone();
 
+    for request in &payload {
+        if let Err(e) = apply_feature_request(&mut device, request) {
+            // keep what got applied undoable
+            state.history.lock().await.record("Set features", before, device.features.clone());
+            state.saver.request();
+            return e.into_response();
+        }
+    }
+
+    state.history.lock().await.record("Set features", before, device.features.clone());
+    state.saver.request();
+
+    StatusCode::OK.into_response()
+}
+
+fn apply_feature_request(
+    device: &mut BlasterXG6,
+    payload: &SetFeatureRequest,
+) -> Result<(), (StatusCode, String)> {
     if let Some(toggle_val) = payload.toggle {
         if let Err(e) = device.set_feature(&payload.name, Some(toggle_val)) {
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to set feature: {}", e)).into_response();
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to set feature: {}", e)));
         }
     }
 
     if let Some(slider_val) = payload.slider {
         if let Err(e) = device.set_slider(&payload.name, slider_val) {
-             return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to set slider: {}", e)).into_response();
+             return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to set slider: {}", e)));
         }
     }
 
-    let default_profile = device.profile_path.join("default.json");
-    if let Err(e) = device.save_profile(default_profile) {
-        tracing::error!("Failed to save default profile: {}", e);
+    Ok(())
+}
+
+/// Resets all features to their defaults, as a single undoable step
+#[utoipa::path(
+    post, path = "/api/reset", tag = "device",
+    responses((status = 200), (status = 500, body = String))
+)]
+pub async fn reset(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let mut device = state.device.lock().await;
+    let before = device.features.clone();
+
+    // Box<dyn Error> isn't Send, it can't be held across the await below
+    let result = device.reset().map_err(|e| e.to_string());
+    state.history.lock().await.record("Reset", before, device.features.clone());
+    state.saver.request();
+
+    if let Err(e) = result {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to reset: {}", e)).into_response();
+    }
+    StatusCode::OK.into_response()
+}
+
+#[utoipa::path(
+    get, path = "/api/history", tag = "history",
+    responses((status = 200, body = crate::history::HistorySummary))
+)]
+pub async fn get_history(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    Json(state.history.lock().await.summary())
+}
+
+#[utoipa::path(
+    post, path = "/api/undo", tag = "history",
+    responses((status = 200, body = crate::history::HistorySummary), (status = 409, description = "Nothing to undo"), (status = 500, body = String))
+)]
+pub async fn undo(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let mut device = state.device.lock().await;
+    let mut history = state.history.lock().await;
+
+    let Some(step) = history.take_undo() else {
+        return (StatusCode::CONFLICT, "Nothing to undo").into_response();
+    };
+    if let Err(e) = device.transition(&step.before) {
+        history.restore_undo(step);
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to undo: {}", e)).into_response();
+    }
+    history.undone(step);
+    state.saver.request();
+
+    Json(history.summary()).into_response()
+}
+
+#[utoipa::path(
+    post, path = "/api/redo", tag = "history",
+    responses((status = 200, body = crate::history::HistorySummary), (status = 409, description = "Nothing to redo"), (status = 500, body = String))
+)]
+pub async fn redo(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let mut device = state.device.lock().await;
+    let mut history = state.history.lock().await;
+
+    let Some(step) = history.take_redo() else {
+        return (StatusCode::CONFLICT, "Nothing to redo").into_response();
+    };
+    if let Err(e) = device.transition(&step.after) {
+        history.restore_redo(step);
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to redo: {}", e)).into_response();
+    }
+    history.redone(step);
+    state.saver.request();
+
+    Json(history.summary()).into_response()
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ProfileInfo {
+    pub name: String,
+    /// `None` if the file can't be read, see `error`
+    pub layers: Option<Vec<Layer>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Built-in presets can be applied and cloned, but not changed
+    pub read_only: bool,
+}
+
+/// Status for an error from resolving or loading a named profile
+fn profile_error_status(e: &(dyn std::error::Error + 'static)) -> StatusCode {
+    match e.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+        Some(std::io::ErrorKind::NotFound) => StatusCode::NOT_FOUND,
+        Some(std::io::ErrorKind::InvalidInput) => StatusCode::BAD_REQUEST,
+        Some(std::io::ErrorKind::PermissionDenied) => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Profile switches would overwrite A or B of a running ABX session
+async fn check_no_abx(state: &AppState) -> Result<(), axum::response::Response> {
+    if state.abx.lock().await.is_some() {
+        return Err((StatusCode::CONFLICT, "An ABX session is running, stop it first").into_response());
+    }
+    Ok(())
+}
+
+/// User profiles first, then the built-in presets
+#[utoipa::path(
+    get, path = "/api/profiles", tag = "profiles",
+    responses((status = 200, body = Vec<ProfileInfo>))
+)]
+pub async fn list_profiles(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let device = state.device.lock().await;
+
+    let profiles: Vec<ProfileInfo> = device
+        .list_profiles()
+        .into_iter()
+        .map(|name| {
+            let loaded = device
+                .named_profile_path(&name)
+                .and_then(|path| Profile::load(&path));
+            match loaded {
+                Ok(profile) => ProfileInfo {
+                    name,
+                    layers: Some(profile.layers()),
+                    error: None,
+                    read_only: false,
+                },
+                Err(e) => ProfileInfo {
+                    name,
+                    layers: None,
+                    error: Some(e.to_string()),
+                    read_only: false,
+                },
+            }
+        })
+        .chain(presets::names().into_iter().map(|name| ProfileInfo {
+            layers: presets::preset(&name).map(|profile| profile.layers()),
+            name,
+            error: None,
+            read_only: true,
+        }))
+        .collect();
+
+    Json(profiles)
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ApplyProfileRequest {
+    pub name: String,
+    /// Layers to take from the profile, all of them if omitted
+    pub layers: Option<Vec<Layer>>,
+}
+
+#[utoipa::path(
+    post, path = "/api/profile/apply", tag = "profiles", request_body = ApplyProfileRequest,
+    responses((status = 200, description = "The active layers", body = std::collections::BTreeMap<Layer, ActiveLayer>), (status = 404, body = String), (status = 409, description = "Superseded by another profile switch, or an ABX session is running"), (status = 500, body = String))
+)]
+pub async fn apply_profile(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ApplyProfileRequest>,
+) -> impl IntoResponse {
+    apply_profile_stack(state, vec![payload]).await
+}
+
+/// Applies several profiles in order, later ones win where layers overlap.
+/// e.g. the EQ layer of one profile on top of the effects of another
+#[utoipa::path(
+    post, path = "/api/profile/stack", tag = "profiles", request_body = Vec<ApplyProfileRequest>,
+    responses((status = 200, description = "The active layers", body = std::collections::BTreeMap<Layer, ActiveLayer>), (status = 404, body = String), (status = 409, description = "Superseded by another profile switch, or an ABX session is running"), (status = 500, body = String))
+)]
+pub async fn stack_profiles(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<Vec<ApplyProfileRequest>>,
+) -> impl IntoResponse {
+    apply_profile_stack(state, payload).await
+}
+
+async fn apply_profile_stack(
+    state: Arc<AppState>,
+    stack: Vec<ApplyProfileRequest>,
+) -> axum::response::Response {
+    let generation = state.ramp.start();
+    let label = format!(
+        "Apply profile {}",
+        stack.iter().map(|e| e.name.as_str()).collect::<Vec<_>>().join(" + ")
+    );
+
+    let mut device = state.device.lock().await;
+    if let Err(response) = check_no_abx(&state).await {
+        return response;
+    }
+    let before = device.features.clone();
+    let mut profiles = Vec::new();
+    for entry in &stack {
+        let mut profile = match device.open_named_profile(&entry.name).map_err(|e| (profile_error_status(&*e), e.to_string())) {
+            Ok(profile) => profile,
+            Err((status, e)) => return (status, format!("Failed to apply profile {}: {}", entry.name, e)).into_response(),
+        };
+        if let Some(layers) = &entry.layers {
+            profile.retain_layers(layers);
+        }
+        profiles.push(profile);
+    }
+
+    // morph the EQ first, the device is only locked per step meanwhile
+    let duration = std::time::Duration::from_millis(config::get().transition_ms);
+    if !duration.is_zero()
+        && let Some(target) = ramp::eq_target(&device, &profiles)
+    {
+        drop(device);
+        if !ramp::ramp_eq(&state, target, duration, generation).await {
+            return (StatusCode::CONFLICT, "Superseded by another profile switch").into_response();
+        }
+        device = state.device.lock().await;
+    }
+
+    for (entry, profile) in stack.iter().zip(&profiles) {
+        if let Err(e) = device.apply_layered_profile(&entry.name, profile).map_err(|e| e.to_string()) {
+            state.history.lock().await.record(label, before, device.features.clone());
+            state.saver.request();
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to apply profile {}: {}", entry.name, e)).into_response();
+        }
+    }
+
+    state.history.lock().await.record(label, before, device.features.clone());
+    state.saver.request();
+    Json(device.active_layers.clone()).into_response()
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SaveProfileRequest {
+    pub name: String,
+    /// Layers to store, all except the mixer if omitted
+    pub layers: Option<Vec<Layer>>,
+}
+
+#[utoipa::path(
+    post, path = "/api/profile/save", tag = "profiles", request_body = SaveProfileRequest,
+    responses((status = 200), (status = 400, body = String), (status = 403, description = "Built-in presets are read-only"), (status = 500, body = String))
+)]
+pub async fn save_profile(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SaveProfileRequest>,
+) -> impl IntoResponse {
+    let device = state.device.lock().await;
+
+    let path = match device.named_profile_path(&payload.name) {
+        Ok(path) => path,
+        Err(e) => return (profile_error_status(&*e), e.to_string()).into_response(),
+    };
+    let layers = payload
+        .layers
+        .unwrap_or_else(|| vec![Layer::Effects, Layer::Routing, Layer::Eq]);
+
+    if let Err(e) = device.save_layered_profile(path, &layers) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save profile: {}", e)).into_response();
+    }
+
+    StatusCode::OK.into_response()
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CloneProfileRequest {
+    /// Profile or built-in preset to copy
+    pub name: String,
+    pub save_as: String,
+    /// Replace an existing profile called `save_as`
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Copies a profile as stored, e.g. a built-in preset to a user profile
+/// that can then be changed
+#[utoipa::path(
+    post, path = "/api/profile/clone", tag = "profiles", request_body = CloneProfileRequest,
+    responses((status = 200), (status = 400, body = String), (status = 403, description = "Built-in presets are read-only"), (status = 404, body = String), (status = 409, description = "The profile already exists"), (status = 500, body = String))
+)]
+pub async fn clone_profile(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CloneProfileRequest>,
+) -> impl IntoResponse {
+    let device = state.device.lock().await;
+
+    let profile = match presets::load_named(&device.profile_path, &payload.name) {
+        Ok(profile) => profile,
+        Err(e) => return (profile_error_status(&*e), e.to_string()).into_response(),
+    };
+    let path = match device.named_profile_path(&payload.save_as) {
+        Ok(path) => path,
+        Err(e) => return (profile_error_status(&*e), e.to_string()).into_response(),
+    };
+    if path.exists() && !payload.overwrite {
+        return (StatusCode::CONFLICT, format!("Profile {} already exists", payload.save_as)).into_response();
+    }
+
+    let saved = serde_json::to_vec_pretty(&profile)
+        .map_err(|e| e.into())
+        .and_then(|json| crate::write_atomic(&path, &json));
+    if let Err(e) = saved {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save profile: {}", e)).into_response();
+    }
+
+    StatusCode::OK.into_response()
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AppRulesResponse {
+    pub rules: Vec<AppRule>,
+    /// The rule whose profile is applied right now
+    pub engaged: Option<AppRule>,
+}
+
+#[utoipa::path(
+    get, path = "/api/rules", tag = "rules",
+    responses((status = 200, body = AppRulesResponse))
+)]
+pub async fn get_app_rules(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let auto = state.autoswitch.lock().await;
+    Json(AppRulesResponse {
+        rules: auto.rules.clone(),
+        engaged: auto.engaged_rule().cloned(),
+    })
+}
+
+/// Replaces all rules, they're checked in the given order
+#[utoipa::path(
+    put, path = "/api/rules", tag = "rules", request_body = Vec<AppRule>,
+    responses((status = 200), (status = 400, body = String), (status = 500, body = String))
+)]
+pub async fn set_app_rules(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<Vec<AppRule>>,
+) -> impl IntoResponse {
+    for rule in &payload {
+        if let Err(e) = rule.validate() {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    }
+
+    let path = {
+        let device = state.device.lock().await;
+        for rule in &payload {
+            if let Err(e) = presets::validate_name(&device.profile_path, &rule.profile) {
+                return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+            }
+        }
+        autoswitch::rules_path(&device.profile_path)
+    };
+
+    let mut auto = state.autoswitch.lock().await;
+    auto.rules = payload;
+    if let Err(e) = auto.save(&path) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save rules: {}", e)).into_response();
+    }
+
+    StatusCode::OK.into_response()
+}
+
+/// The streams currently on the G6, to find out what to match on
+#[utoipa::path(
+    get, path = "/api/rules/streams", tag = "rules",
+    responses((status = 200, body = Vec<PulseStream>))
+)]
+pub async fn get_streams() -> impl IntoResponse {
+    let streams = tokio::task::spawn_blocking(list_g6_streams)
+        .await
+        .unwrap_or_default();
+    Json(streams)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SchedulesResponse {
+    pub schedules: Vec<Schedule>,
+    /// The schedules whose window is open right now
+    pub engaged: Vec<Schedule>,
+}
+
+#[utoipa::path(
+    get, path = "/api/schedules", tag = "schedules",
+    responses((status = 200, body = SchedulesResponse))
+)]
+pub async fn get_schedules(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let scheduler = state.scheduler.lock().await;
+    Json(SchedulesResponse {
+        schedules: scheduler.schedules.clone(),
+        engaged: scheduler.engaged(),
+    })
+}
+
+/// Replaces all schedules.
+/// Windows of removed schedules close, windows of new ones open
+/// on the next check, which reverts / applies their profiles.
+#[utoipa::path(
+    put, path = "/api/schedules", tag = "schedules", request_body = Vec<Schedule>,
+    responses((status = 200), (status = 400, body = String), (status = 500, body = String))
+)]
+pub async fn set_schedules(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<Vec<Schedule>>,
+) -> impl IntoResponse {
+    for entry in &payload {
+        if let Err(e) = entry.validate() {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    }
+
+    let path = {
+        let device = state.device.lock().await;
+        for entry in &payload {
+            if let Err(e) = presets::validate_name(&device.profile_path, &entry.profile) {
+                return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+            }
+        }
+        schedule::schedules_path(&device.profile_path)
+    };
+
+    let mut scheduler = state.scheduler.lock().await;
+    scheduler.schedules = payload;
+    if let Err(e) = scheduler.save(&path) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save schedules: {}", e)).into_response();
     }
 
-    // Success
     StatusCode::OK.into_response()
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ShareExportQuery {
+    /// Profile to export, the current state if omitted
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ShareCodeResponse {
+    pub code: String,
+}
+
+#[utoipa::path(
+    get, path = "/api/share", tag = "share", params(ShareExportQuery),
+    responses((status = 200, body = ShareCodeResponse), (status = 404, body = String), (status = 500, body = String))
+)]
+pub async fn export_share_code(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ShareExportQuery>,
+) -> impl IntoResponse {
+    let device = state.device.lock().await;
+
+    let mut profile = match query.name {
+        None => device.to_profile(&[Layer::Effects, Layer::Routing, Layer::Eq]),
+        Some(name) => match presets::load_named(&device.profile_path, &name) {
+            Ok(profile) => profile,
+            Err(e) => return (profile_error_status(&*e), format!("Failed to load profile {}: {}", name, e)).into_response(),
+        },
+    };
+    // share codes carry the bands, not the tone controls
+    if let Err(e) = tone::resolve(&mut profile, &device.profile_path) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to resolve tone controls: {}", e)).into_response();
+    }
+
+    Json(ShareCodeResponse { code: share::encode(&profile) }).into_response()
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ShareImportRequest {
+    pub code: String,
+    /// Apply the decoded profile to the device
+    #[serde(default)]
+    pub apply: bool,
+    /// Save the decoded profile under this name
+    pub save_as: Option<String>,
+}
+
+/// Decodes a share code, the decoded profile is returned in any case.
+/// A code that doesn't decode never touches the device or the disk.
+#[utoipa::path(
+    post, path = "/api/share/import", tag = "share", request_body = ShareImportRequest,
+    responses((status = 200, description = "The decoded profile", body = Profile), (status = 400, body = String), (status = 409, description = "An ABX session is running"), (status = 500, body = String))
+)]
+pub async fn import_share_code(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ShareImportRequest>,
+) -> impl IntoResponse {
+    let profile = match share::decode(&payload.code) {
+        Ok(profile) => profile,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Invalid share code: {}", e)).into_response(),
+    };
+
+    let mut device = state.device.lock().await;
+    if payload.apply
+        && let Err(response) = check_no_abx(&state).await
+    {
+        return response;
+    }
+
+    if let Some(name) = &payload.save_as {
+        let path = match device.named_profile_path(name) {
+            Ok(path) => path,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        };
+        let saved = serde_json::to_vec_pretty(&profile)
+            .map_err(|e| e.into())
+            .and_then(|json| crate::write_atomic(&path, &json));
+        if let Err(e) = saved {
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save profile: {}", e)).into_response();
+        }
+    }
+
+    if payload.apply {
+        let name = payload.save_as.as_deref().unwrap_or("share code");
+        let before = device.features.clone();
+        let result = device.apply_layered_profile(name, &profile).map_err(|e| e.to_string());
+        state.history.lock().await.record("Import share code", before, device.features.clone());
+        state.saver.request();
+        if let Err(e) = result {
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to apply share code: {}", e)).into_response();
+        }
+    }
+
+    Json(profile).into_response()
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConfigResponse {
+    pub config: Config,
+    /// Where changes are stored
+    #[schema(value_type = Option<String>)]
+    pub path: Option<std::path::PathBuf>,
+}
+
+#[utoipa::path(
+    get, path = "/api/config", tag = "config",
+    responses((status = 200, body = ConfigResponse))
+)]
+pub async fn get_config() -> impl IntoResponse {
+    Json(ConfigResponse {
+        config: config::get(),
+        path: config::config_path().map(|p| p.to_path_buf()),
+    })
+}
+
+/// Changes the settings that are safe to change while running
+#[utoipa::path(
+    put, path = "/api/config", tag = "config", request_body = ConfigUpdate,
+    responses((status = 200, body = Config), (status = 400, body = String))
+)]
+pub async fn set_config(Json(payload): Json<ConfigUpdate>) -> impl IntoResponse {
+    match tokio::task::spawn_blocking(move || config::update(&payload).map_err(|e| e.to_string())).await {
+        Ok(Ok(config)) => Json(config).into_response(),
+        Ok(Err(e)) => (StatusCode::BAD_REQUEST, e).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AutoEqSearchQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[utoipa::path(
+    get, path = "/api/autoeq/search", tag = "autoeq", params(AutoEqSearchQuery),
+    responses((status = 200, body = Vec<autoeq::Headphone>))
+)]
+pub async fn search_autoeq(Query(query): Query<AutoEqSearchQuery>) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(autoeq::SEARCH_LIMIT).min(autoeq::SEARCH_LIMIT);
+    Json(autoeq::search(&query.q, limit))
+}
+
+#[utoipa::path(
+    get, path = "/api/autoeq", tag = "autoeq",
+    responses((status = 200, body = autoeq::DatabaseInfo))
+)]
+pub async fn get_autoeq_info() -> impl IntoResponse {
+    Json(autoeq::info())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AutoEqLoadRequest {
+    /// `autoeq_mode` of the config if omitted
+    pub mode: Option<autoeq::LoadMode>,
+}
+
+/// Reloads the results at `autoeq_path` of the config, to refresh headphone
+/// data without a rebuild. Other paths are only taken from the command line
+/// and the config file, any web page may send requests here.
+#[utoipa::path(
+    post, path = "/api/autoeq/load", tag = "autoeq", request_body = AutoEqLoadRequest,
+    responses((status = 200, body = autoeq::DatabaseInfo), (status = 400, body = String))
+)]
+pub async fn load_autoeq(Json(payload): Json<AutoEqLoadRequest>) -> impl IntoResponse {
+    let config = config::get();
+    let Some(path) = config.autoeq_path else {
+        return (StatusCode::BAD_REQUEST, "No autoeq_path configured").into_response();
+    };
+    let mode = payload.mode.unwrap_or(config.autoeq_mode);
+    let loaded = tokio::task::spawn_blocking(move || {
+        autoeq::load(&path, mode).map_err(|e| e.to_string())
+    })
+    .await;
+    match loaded {
+        Ok(Ok(info)) => Json(info).into_response(),
+        Ok(Err(e)) => (StatusCode::BAD_REQUEST, format!("Failed to load AutoEq results: {}", e)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Drops runtime results, only the built-in table is left
+#[utoipa::path(
+    post, path = "/api/autoeq/reset", tag = "autoeq",
+    responses((status = 200, body = autoeq::DatabaseInfo))
+)]
+pub async fn reset_autoeq() -> impl IntoResponse {
+    Json(autoeq::reset())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AutoEqSimilarQuery {
+    /// Reference headphone, the current EQ if omitted
+    pub name: Option<String>,
+    /// Picks the measurement of the reference, the first one if omitted
+    pub variant: Option<String>,
+    /// Only measurements of this tester and / or rig, reference included
+    pub tester: Option<String>,
+    pub test_device: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Headphones with a correction close to a reference headphone or the current EQ
+#[utoipa::path(
+    get, path = "/api/autoeq/similar", tag = "autoeq", params(AutoEqSimilarQuery),
+    responses((status = 200, body = Vec<autoeq::SimilarHeadphone>), (status = 404, body = String))
+)]
+pub async fn similar_autoeq(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AutoEqSimilarQuery>,
+) -> impl IntoResponse {
+    let filter = autoeq::MeasurementFilter {
+        tester: query.tester.as_deref(),
+        test_device: query.test_device.as_deref(),
+    };
+    let limit = query.limit.unwrap_or(autoeq::SEARCH_LIMIT).min(autoeq::SEARCH_LIMIT);
+
+    let bands = match &query.name {
+        Some(name) => {
+            let Some(headphone) = autoeq::find(name) else {
+                return (StatusCode::NOT_FOUND, format!("Headphone {} not found", name)).into_response();
+            };
+            let reference = headphone.results.iter().find(|r| {
+                filter.matches(r) && query.variant.as_deref().is_none_or(|v| Some(v) == r.variant.as_deref())
+            });
+            match reference {
+                Some(reference) => reference.ten_band_eq,
+                None => return (StatusCode::NOT_FOUND, format!("No matching measurement for {}", name)).into_response(),
+            }
+        }
+        None => {
+            let mut bands = [0.0; 10];
+            bands.copy_from_slice(&state.device.lock().await.active_ten_band_eq()[1..]);
+            bands
+        }
+    };
+
+    Json(autoeq::similar(&bands, filter, query.name.as_deref(), limit)).into_response()
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AutoEqApplyRequest {
+    /// Exact headphone name, as returned by the search
+    pub name: String,
+    /// Picks one of several measurements, the first one if omitted
+    pub tester: Option<String>,
+    pub variant: Option<String>,
+    pub test_device: Option<String>,
+}
+
+/// Applies an AutoEq result to the Pre-Amp and the EQ Bands, clamped to ±12 dB
+#[utoipa::path(
+    post, path = "/api/autoeq/apply", tag = "autoeq", request_body = AutoEqApplyRequest,
+    responses((status = 200, description = "Pre-Amp and bands in dB", body = [f32]), (status = 404, body = String), (status = 409, description = "An ABX session is running"), (status = 500, body = String))
+)]
+pub async fn apply_autoeq(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<AutoEqApplyRequest>,
+) -> impl IntoResponse {
+    let Some(headphone) = autoeq::find(&payload.name) else {
+        return (StatusCode::NOT_FOUND, format!("Headphone {} not found", payload.name)).into_response();
+    };
+    let result = headphone.results.iter().find(|r| {
+        payload.tester.as_deref().is_none_or(|t| t == r.tester)
+            && payload.variant.as_deref().is_none_or(|v| Some(v) == r.variant.as_deref())
+            && payload.test_device.as_deref().is_none_or(|d| Some(d) == r.test_device.as_deref())
+    });
+    let Some(result) = result else {
+        return (StatusCode::NOT_FOUND, format!("No matching measurement for {}", payload.name)).into_response();
+    };
+
+    let mut bands = [result.preamp; 11];
+    bands[1..].copy_from_slice(&result.ten_band_eq);
+
+    let mut device = state.device.lock().await;
+    if let Err(response) = check_no_abx(&state).await {
+        return response;
+    }
+    let before = device.features.clone();
+    let applied = device.set_ten_band_eq(bands).map_err(|e| e.to_string());
+    state.history.lock().await.record(format!("Apply AutoEq {}", payload.name), before, device.features.clone());
+    state.saver.request();
+
+    match applied {
+        Ok(()) => Json(device.get_ten_band_eq()).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to apply AutoEq result: {}", e)).into_response(),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct FitRequest {
+    pub filters: Vec<fit::Filter>,
+    /// Apply the fitted bands to the device
+    #[serde(default)]
+    pub apply: bool,
+}
+
+/// Fits a parametric EQ onto the 10 bands, returns the bands and the residual error
+#[utoipa::path(
+    post, path = "/api/eq/fit", tag = "eq", request_body = FitRequest,
+    responses((status = 200, body = fit::FitResult), (status = 400, body = String), (status = 409, description = "An ABX session is running"), (status = 500, body = String))
+)]
+pub async fn fit_eq(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<FitRequest>,
+) -> impl IntoResponse {
+    for filter in &payload.filters {
+        if let Err(e) = filter.validate() {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    }
+    let result = fit::fit_filters(&payload.filters);
+
+    if payload.apply {
+        let mut device = state.device.lock().await;
+        if let Err(response) = check_no_abx(&state).await {
+            return response;
+        }
+        let before = device.features.clone();
+        let applied = device.set_ten_band_eq(result.bands).map_err(|e| e.to_string());
+        state.history.lock().await.record("Apply fitted EQ", before, device.features.clone());
+        state.saver.request();
+        if let Err(e) = applied {
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to apply fitted EQ: {}", e)).into_response();
+        }
+    }
+
+    Json(result).into_response()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResponseQuery {
+    pub points: Option<usize>,
+}
+
+/// [`BlasterXG6::active_ten_band_eq`], or the EQ from before a running
+/// ABX session, the one on the device would give X away
+async fn visible_ten_band_eq(state: &AppState) -> [f32; 11] {
+    let device = state.device.lock().await;
+    match state.abx.lock().await.as_ref() {
+        Some(session) => session.masked_ten_band_eq(),
+        None => device.active_ten_band_eq(),
+    }
+}
+
+/// Response of the EQ as it is active right now
+#[utoipa::path(
+    get, path = "/api/eq/response", tag = "eq", params(ResponseQuery),
+    responses((status = 200, body = eq::Response))
+)]
+pub async fn get_eq_response(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ResponseQuery>,
+) -> impl IntoResponse {
+    let bands = visible_ten_band_eq(&state).await;
+    Json(eq::response(&bands, query.points.unwrap_or(eq::RESPONSE_POINTS)))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ResponseRequest {
+    /// Pre-Amp and bands in dB, the current EQ if not given
+    pub bands: Option<[f32; 11]>,
+    pub points: Option<usize>,
+}
+
+/// Response of a proposed Pre-Amp and band set, nothing is applied
+#[utoipa::path(
+    post, path = "/api/eq/response", tag = "eq", request_body = ResponseRequest,
+    responses((status = 200, body = eq::Response), (status = 400, body = String))
+)]
+pub async fn eq_response(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ResponseRequest>,
+) -> impl IntoResponse {
+    let bands = match payload.bands {
+        Some(bands) => bands,
+        None => visible_ten_band_eq(&state).await,
+    };
+    if bands.iter().any(|gain| !gain.is_finite() || gain.abs() > eq::MAX_GAIN) {
+        return (StatusCode::BAD_REQUEST, format!("Gains must be within ±{} dB", eq::MAX_GAIN)).into_response();
+    }
+    Json(eq::response(&bands, payload.points.unwrap_or(eq::RESPONSE_POINTS))).into_response()
+}
+
+#[utoipa::path(
+    get, path = "/api/eq/auto_preamp", tag = "eq",
+    responses((status = 200, body = crate::preamp::AutoPreAmp))
+)]
+pub async fn get_auto_preamp(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.device.lock().await.auto_preamp.clone())
+}
+
+/// Changes the auto Pre-Amp settings and moves the Pre-Amp accordingly
+#[utoipa::path(
+    put, path = "/api/eq/auto_preamp", tag = "eq", request_body = AutoPreAmpUpdate,
+    responses((status = 200, body = crate::preamp::AutoPreAmp), (status = 400, body = String), (status = 500, body = String))
+)]
+pub async fn set_auto_preamp(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<AutoPreAmpUpdate>,
+) -> impl IntoResponse {
+    let mut device = state.device.lock().await;
+    if let Err(e) = device.auto_preamp.update(&payload).map_err(|e| e.to_string()) {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+
+    device.publish_eq();
+
+    let before = device.features.clone();
+    let applied = device.update_auto_preamp().map_err(|e| e.to_string());
+    state.history.lock().await.record("Auto Pre-Amp", before, device.features.clone());
+    state.saver.request();
+    if let Err(e) = applied {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to set Pre-Amp: {}", e)).into_response();
+    }
+
+    Json(device.auto_preamp.clone()).into_response()
+}
+
+#[utoipa::path(
+    get, path = "/api/eq/loudness", tag = "eq",
+    responses((status = 200, body = crate::loudness::Loudness))
+)]
+pub async fn get_loudness(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.device.lock().await.loudness.clone())
+}
+
+/// Changes the loudness compensation settings, the bands themselves stay
+#[utoipa::path(
+    put, path = "/api/eq/loudness", tag = "eq", request_body = LoudnessUpdate,
+    responses((status = 200, body = crate::loudness::Loudness), (status = 400, body = String), (status = 500, body = String))
+)]
+pub async fn set_loudness(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<LoudnessUpdate>,
+) -> impl IntoResponse {
+    let mut device = state.device.lock().await;
+    if let Err(e) = device.loudness.settings.update(&payload).map_err(|e| e.to_string()) {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+    state.saver.request();
+    if let Err(e) = device.update_loudness(None).map_err(|e| e.to_string()) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to apply loudness compensation: {}", e)).into_response();
+    }
+
+    Json(device.loudness.clone()).into_response()
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ToneResponse {
+    pub tone: Option<ToneControls>,
+    /// Pre-Amp and bands the tone controls result in
+    pub bands: [f32; 11],
+}
+
+/// The tone controls the current EQ came from, `None` if it was changed otherwise
+#[utoipa::path(
+    get, path = "/api/eq/tone", tag = "eq",
+    responses((status = 200, body = ToneResponse))
+)]
+pub async fn get_tone(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let device = state.device.lock().await;
+    Json(ToneResponse {
+        tone: device.tone.clone(),
+        bands: device.get_ten_band_eq().unwrap_or([0.0; 11]),
+    })
+}
+
+/// Sets the bands from tone controls on top of their base profile
+#[utoipa::path(
+    put, path = "/api/eq/tone", tag = "eq", request_body = ToneControls,
+    responses((status = 200, body = ToneResponse), (status = 400, body = String), (status = 409, description = "An ABX session is running"), (status = 500, body = String))
+)]
+pub async fn set_tone(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ToneControls>,
+) -> impl IntoResponse {
+    if let Err(e) = payload.validate().map_err(|e| e.to_string()) {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+
+    let mut device = state.device.lock().await;
+    if let Err(response) = check_no_abx(&state).await {
+        return response;
+    }
+    let base = match &payload.base {
+        Some(name) => match tone::profile_bands(&device.profile_path, name).map_err(|e| e.to_string()) {
+            Ok(bands) => bands,
+            Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        },
+        None => [0.0; 11],
+    };
+    let bands = payload.ten_band_eq(&base);
+
+    let before = device.features.clone();
+    let applied = device.set_ten_band_eq(bands).map_err(|e| e.to_string());
+    if applied.is_ok() {
+        device.tone = Some(payload);
+        device.publish_eq();
+    }
+    state.history.lock().await.record("Tone controls", before, device.features.clone());
+    state.saver.request();
+    if let Err(e) = applied {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to apply tone controls: {}", e)).into_response();
+    }
+
+    Json(ToneResponse {
+        tone: device.tone.clone(),
+        bands: device.get_ten_band_eq().unwrap_or(bands),
+    })
+    .into_response()
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// Apply the imported EQ to the device
+    #[serde(default)]
+    pub apply: bool,
+    /// Save the imported EQ as a profile with this name
+    pub save_as: Option<String>,
+}
+
+/// Imports an Equalizer APO / Peace config.txt, sent as the request body
+#[utoipa::path(
+    post, path = "/api/eq/import/apo", tag = "eq", params(ImportQuery),
+    request_body(content = String, description = "Equalizer APO config.txt", content_type = "text/plain"),
+    responses((status = 200, body = apo::ApoImport), (status = 400, body = String), (status = 409, description = "An ABX session is running"), (status = 500, body = String))
+)]
+pub async fn import_apo(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> impl IntoResponse {
+    let import = apo::import(&body);
+    if let Err(response) = apply_or_save_eq(&state, &import.profile, &query, "Import Equalizer APO config").await {
+        return response;
+    }
+    Json(import).into_response()
+}
+
+#[utoipa::path(
+    get, path = "/api/eq/targets", tag = "eq",
+    responses((status = 200, description = "Bundled target curves", body = Vec<String>))
+)]
+pub async fn get_targets() -> impl IntoResponse {
+    Json(target::builtin_targets())
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TargetRequest {
+    /// Measurement CSV of the headphone
+    pub measurement: String,
+    /// Bundled target, see `/api/eq/targets`, [`target::DEFAULT_TARGET`] if not given
+    pub target: Option<String>,
+    /// Target CSV, instead of `target`
+    pub target_csv: Option<String>,
+}
+
+/// Fits the bands to correct a headphone measurement towards a target curve
+#[utoipa::path(
+    post, path = "/api/eq/target", tag = "eq", params(ImportQuery), request_body = TargetRequest,
+    responses((status = 200, body = target::TargetEq), (status = 400, body = String), (status = 409, description = "An ABX session is running"), (status = 500, body = String))
+)]
+pub async fn target_eq(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ImportQuery>,
+    Json(payload): Json<TargetRequest>,
+) -> impl IntoResponse {
+    let curves = target::Curve::parse(&payload.measurement)
+        .map_err(|e| format!("Invalid measurement: {}", e))
+        .and_then(|measurement| {
+            // bundled targets only, any web page can send requests here and must not read local files
+            let target = match &payload.target_csv {
+                Some(csv) => target::Curve::parse(csv).map_err(|e| format!("Invalid target: {}", e)),
+                None => {
+                    let name = payload.target.as_deref().unwrap_or(target::DEFAULT_TARGET);
+                    target::Curve::builtin(name).ok_or_else(|| {
+                        format!("Unknown target {}, expected one of {}", name, target::builtin_targets().join(", "))
+                    })
+                }
+            };
+            target.map(|target| (measurement, target))
+        });
+    let (measurement, target) = match curves {
+        Ok(curves) => curves,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let result = target::target_eq(&measurement, &target);
+    if let Err(response) = apply_or_save_eq(&state, &result.profile, &query, "Apply target curve EQ").await {
+        return response;
+    }
+    Json(result).into_response()
+}
+
+/// Saves and / or applies an EQ only profile, as asked for by an import
+async fn apply_or_save_eq(
+    state: &AppState,
+    profile: &Profile,
+    query: &ImportQuery,
+    label: &str,
+) -> Result<(), axum::response::Response> {
+    let mut device = state.device.lock().await;
+    if query.apply {
+        check_no_abx(state).await?;
+    }
+
+    if let Some(name) = &query.save_as {
+        let path = device
+            .named_profile_path(name)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+        serde_json::to_vec_pretty(profile)
+            .map_err(|e| e.into())
+            .and_then(|json| crate::write_atomic(&path, &json))
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save profile: {}", e)).into_response())?;
+    }
+
+    if query.apply {
+        let name = query.save_as.as_deref().unwrap_or("import");
+        let before = device.features.clone();
+        let result = device.apply_layered_profile(name, profile).map_err(|e| e.to_string());
+        state.history.lock().await.record(label, before, device.features.clone());
+        state.saver.request();
+        result.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to apply EQ: {}", e)).into_response())?;
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get, path = "/api/eq/export/pipewire", tag = "eq",
+    responses((status = 200, description = "PipeWire filter-chain config", body = String, content_type = "text/plain"))
+)]
+pub async fn export_pipewire(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let bands = visible_ten_band_eq(&state).await;
+    (
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"g6-eq.conf\""),
+        ],
+        export::pipewire_filter_chain(&bands),
+    )
+}
+
+#[utoipa::path(
+    get, path = "/api/eq/export/easyeffects", tag = "eq",
+    responses((status = 200, description = "EasyEffects preset", body = serde_json::Value))
+)]
+pub async fn export_easyeffects(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let bands = visible_ten_band_eq(&state).await;
+    (
+        [(header::CONTENT_DISPOSITION, "attachment; filename=\"G6.json\"")],
+        Json(export::easyeffects_preset(&bands)),
+    )
+}
+
+#[utoipa::path(
+    get, path = "/api/abx", tag = "abx",
+    responses((status = 200, body = abx::AbxStatus), (status = 404, description = "No ABX session"))
+)]
+pub async fn get_abx(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    match state.abx.lock().await.as_ref() {
+        Some(session) => Json(session.status()).into_response(),
+        None => (StatusCode::NOT_FOUND, "No ABX session").into_response(),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AbxStartRequest {
+    /// Profiles or built-in presets, only their EQ layers are compared
+    pub a: String,
+    pub b: String,
+    pub trials: Option<usize>,
+}
+
+/// Starts a session, the device doesn't change until A, B or X is selected.
+/// The auto Pre-Amp is suspended meanwhile, A and B are level-matched instead.
+#[utoipa::path(
+    post, path = "/api/abx/start", tag = "abx", request_body = AbxStartRequest,
+    responses((status = 200, body = abx::AbxStatus), (status = 400, body = String), (status = 404, body = String), (status = 409, description = "An ABX session is already running"))
+)]
+pub async fn start_abx(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<AbxStartRequest>,
+) -> impl IntoResponse {
+    let mut device = state.device.lock().await;
+    let mut abx = state.abx.lock().await;
+    if abx.is_some() {
+        return (StatusCode::CONFLICT, "An ABX session is already running").into_response();
+    }
+
+    let mut profiles = Vec::new();
+    for name in [&payload.a, &payload.b] {
+        match device.open_named_profile(name).map_err(|e| (profile_error_status(&*e), e.to_string())) {
+            Ok(profile) => profiles.push(profile),
+            Err((status, e)) => return (status, format!("Failed to load profile {}: {}", name, e)).into_response(),
+        }
+    }
+    let Ok(profiles) = <[Profile; 2]>::try_from(profiles) else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load profiles").into_response();
+    };
+
+    let snapshot = Snapshot {
+        profile: device.to_profile(&[Layer::Eq]),
+        active_layers: device.active_layers.clone(),
+    };
+    let trials = payload.trials.unwrap_or(abx::DEFAULT_TRIALS);
+    let session = match AbxSession::new([payload.a, payload.b], profiles, trials, snapshot) {
+        Ok(session) => session,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    // a ramp still in flight would overwrite the session's EQ
+    state.ramp.start();
+    device.auto_preamp.suspended = true;
+    state.events.set_blind(true);
+    let status = session.status();
+    *abx = Some(session);
+    Json(status).into_response()
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AbxSelectRequest {
+    pub choice: Choice,
+}
+
+/// Applies A, B or X, the response doesn't tell which one X is
+#[utoipa::path(
+    post, path = "/api/abx/select", tag = "abx", request_body = AbxSelectRequest,
+    responses((status = 200, body = abx::AbxStatus), (status = 404, description = "No ABX session"), (status = 500, body = String))
+)]
+pub async fn select_abx(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<AbxSelectRequest>,
+) -> impl IntoResponse {
+    let mut device = state.device.lock().await;
+    let mut abx = state.abx.lock().await;
+    let Some(session) = abx.as_mut() else {
+        return (StatusCode::NOT_FOUND, "No ABX session").into_response();
+    };
+
+    // under a neutral name, the active layers are visible in the status
+    let profile = session.profile(payload.choice).clone();
+    if let Err(e) = device.apply_layered_profile("ABX", &profile) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to apply EQ: {}", e)).into_response();
+    }
+    session.selected = Some(payload.choice);
+    Json(session.status()).into_response()
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AbxGuessRequest {
+    /// What X is, `a` or `b`
+    pub guess: Choice,
+}
+
+/// Records a guess, the result shows up once all trials are done
+#[utoipa::path(
+    post, path = "/api/abx/guess", tag = "abx", request_body = AbxGuessRequest,
+    responses((status = 200, body = abx::AbxStatus), (status = 400, body = String), (status = 404, description = "No ABX session"), (status = 409, description = "All trials are done"))
+)]
+pub async fn guess_abx(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<AbxGuessRequest>,
+) -> impl IntoResponse {
+    let mut abx = state.abx.lock().await;
+    let Some(session) = abx.as_mut() else {
+        return (StatusCode::NOT_FOUND, "No ABX session").into_response();
+    };
+    if session.is_finished() {
+        return (StatusCode::CONFLICT, "All trials are done").into_response();
+    }
+    if let Err(e) = session.guess(payload.guess) {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+    Json(session.status()).into_response()
+}
+
+/// Ends the session and goes back to the EQ from before.
+/// The result is returned even if not all trials are done.
+#[utoipa::path(
+    post, path = "/api/abx/stop", tag = "abx",
+    responses((status = 200, description = "The status with the result", body = abx::AbxStatus), (status = 404, description = "No ABX session"), (status = 500, body = String))
+)]
+pub async fn stop_abx(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut device = state.device.lock().await;
+    let Some(session) = state.abx.lock().await.take() else {
+        return (StatusCode::NOT_FOUND, "No ABX session").into_response();
+    };
+
+    device.auto_preamp.suspended = false;
+    let restored = device.restore(&session.snapshot);
+    state.events.set_blind(false);
+    // changes held back meanwhile, see persist::flush
+    state.saver.request();
+    if let Err(e) = restored {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to restore the EQ: {}", e)).into_response();
+    }
+
+    Json(session.final_status()).into_response()
+}
+
+/// Server-sent events with every change, see [`crate::events::Event`]
+#[utoipa::path(
+    get, path = "/api/events", tag = "events",
+    responses((status = 200, description = "One JSON event per message", body = Event, content_type = "text/event-stream"))
+)]
+pub async fn events(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let stream = state
+        .events
+        .stream()
+        .map(|event| sse::Event::default().json_data(event));
+    Sse::new(stream).keep_alive(sse::KeepAlive::default())
+}
+
 pub async fn not_found() -> impl IntoResponse {
     (StatusCode::NOT_FOUND, "Not Found")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_amixer_levels() {
+        let speaker = "\
+Simple mixer control 'Speaker',0
+  Capabilities: pvolume pswitch
+  Playback channels: Front Left - Front Right
+  Limits: Playback 0 - 255
+  Mono:
+  Front Left: Playback 204 [80%] [-11.00dB] [on]
+  Front Right: Playback 153 [60%] [-17.00dB] [on]
+";
+        let levels = parse_amixer(speaker);
+        assert_eq!(levels.playback_vol_l, Some(0.8));
+        assert_eq!(levels.playback_vol_r, Some(0.6));
+        assert!((levels.playback_vol.unwrap() - 0.7).abs() < 1e-6);
+        assert_eq!(levels.playback_mute, Some(false));
+        assert_eq!(levels.capture_vol, None);
+
+        let mic = "\
+Simple mixer control 'External Mic',0
+  Capabilities: cvolume cvolume-joined cswitch cswitch-joined
+  Mono: Capture 20 [50%] [-10.00dB] [off]
+";
+        let levels = parse_amixer(mic);
+        assert_eq!(levels.playback_vol, None);
+        assert_eq!(levels.capture_vol, Some(0.5));
+        assert_eq!(levels.capture_mute, Some(true));
+    }
+}
@@ -0,0 +1,281 @@
+//! Import of Equalizer APO / Peace `config.txt` files
+//!
+//! ```text
+//! Preamp: -6.2 dB
+//! Filter 1: ON PK Fc 105 Hz Gain 6.0 dB Q 0.70
+//! Filter 2: ON LSC Fc 105 Hz Gain 5.5 dB Q 0.71
+//! GraphicEQ: 20 -2.1; 25 -1.8; 31 -1.5; ...
+//! ```
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::eq::{self, MAX_GAIN};
+use crate::fit::{self, Filter, FitResult};
+use crate::profile::Profile;
+
+/// Q of shelf filters that don't specify one
+const DEFAULT_SHELF_Q: f64 = 0.71;
+
+/// A line that was skipped, and why
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Unsupported {
+    /// 1 based
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+/// What was understood of a config file
+#[derive(Serialize, Clone, Debug, Default, PartialEq, ToSchema)]
+pub struct ApoConfig {
+    /// Sum of all `Preamp:` lines, `None` if there are none
+    pub preamp: Option<f64>,
+    pub filters: Vec<Filter>,
+    /// Frequency / gain points of `GraphicEQ:` lines
+    pub graphic_eq: Vec<(f64, f64)>,
+    pub unsupported: Vec<Unsupported>,
+}
+
+/// The config mapped onto the G6 bands
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct ApoImport {
+    pub fit: FitResult,
+    pub unsupported: Vec<Unsupported>,
+    /// EQ layer with the fitted bands
+    pub profile: Profile,
+}
+
+pub fn parse(content: &str) -> ApoConfig {
+    let mut config = ApoConfig::default();
+
+    for (idx, raw) in content.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut skip = |reason: &str| {
+            config.unsupported.push(Unsupported {
+                line: idx + 1,
+                text: raw.to_string(),
+                reason: reason.to_string(),
+            })
+        };
+
+        let Some((directive, args)) = line.split_once(':') else {
+            skip("Not a directive");
+            continue;
+        };
+        let directive = directive.trim();
+
+        if directive.eq_ignore_ascii_case("Preamp") {
+            match parse_db(args) {
+                Some(gain) => *config.preamp.get_or_insert(0.0) += gain,
+                None => skip("Invalid pre-amp"),
+            }
+        } else if directive.eq_ignore_ascii_case("GraphicEQ") {
+            match parse_graphic_eq(args) {
+                Some(points) => config.graphic_eq.extend(points),
+                None => skip("Invalid GraphicEQ points"),
+            }
+        } else if directive
+            .split_whitespace()
+            .next()
+            .is_some_and(|d| d.eq_ignore_ascii_case("Filter"))
+        {
+            match parse_filter(args) {
+                Ok(Some(filter)) => config.filters.push(filter),
+                Ok(None) => {}
+                Err(reason) => skip(&reason),
+            }
+        } else {
+            // Channel:, Device:, Include:, Delay:, Convolution:, ...
+            skip(&format!("{} is not supported", directive));
+        }
+    }
+
+    config
+}
+
+/// `-6.2 dB` => `-6.2`
+fn parse_db(args: &str) -> Option<f64> {
+    let gain: f64 = args.split_whitespace().next()?.parse().ok()?;
+    gain.is_finite().then_some(gain)
+}
+
+/// `20 -2.1; 25 -1.8; ...`
+fn parse_graphic_eq(args: &str) -> Option<Vec<(f64, f64)>> {
+    args.split(';')
+        .filter(|point| !point.trim().is_empty())
+        .map(|point| {
+            let mut values = point.split_whitespace();
+            let freq: f64 = values.next()?.parse().ok()?;
+            let gain: f64 = values.next()?.parse().ok()?;
+            (freq.is_finite() && freq > 0.0 && gain.is_finite())
+                .then_some((freq, gain))
+        })
+        .collect()
+}
+
+/// `ON PK Fc 105 Hz Gain 6.0 dB Q 0.70`.
+/// `Ok(None)` for filters that are switched off,
+/// filters that fail [`Filter::validate`] are an error.
+fn parse_filter(args: &str) -> Result<Option<Filter>, String> {
+    let tokens: Vec<&str> = args.split_whitespace().collect();
+    let (Some(state), Some(kind)) = (tokens.first(), tokens.get(1)) else {
+        return Err("Incomplete filter".to_string());
+    };
+    if state.eq_ignore_ascii_case("OFF") {
+        return Ok(None);
+    }
+
+    let value_after = |key: &str| -> Option<f64> {
+        let idx = tokens.iter().position(|t| t.eq_ignore_ascii_case(key))?;
+        tokens.get(idx + 1)?.parse().ok()
+    };
+    let freq = value_after("Fc").ok_or("Filter without Fc")?;
+    let gain = value_after("Gain");
+    let q = value_after("Q");
+
+    let filter = match kind.to_ascii_uppercase().as_str() {
+        "PK" | "PEQ" | "MODAL" => Filter::Peaking {
+            freq,
+            gain: gain.ok_or("Peaking filter without Gain")?,
+            q: q.ok_or("Peaking filter without Q, BW is not supported")?,
+        },
+        "LS" | "LSC" => Filter::LowShelf {
+            freq,
+            gain: gain.ok_or("Shelf filter without Gain")?,
+            q: q.unwrap_or(DEFAULT_SHELF_Q),
+        },
+        "HS" | "HSC" => Filter::HighShelf {
+            freq,
+            gain: gain.ok_or("Shelf filter without Gain")?,
+            q: q.unwrap_or(DEFAULT_SHELF_Q),
+        },
+        other => return Err(format!("Filter type {} is not supported", other)),
+    };
+    filter.validate().map_err(|e| e.to_string())?;
+    Ok(Some(filter))
+}
+
+impl ApoConfig {
+    /// Target response in dB, filters and GraphicEQ combined
+    pub fn response(&self, freq: f64) -> f64 {
+        fit::filters_response(&self.filters, freq)
+            + eq::interpolate(&self.graphic_eq, freq)
+    }
+
+    /// Maps the config onto the G6 bands.
+    /// The pre-amp of the file is kept if it has one.
+    pub fn to_ten_band_eq(&self) -> FitResult {
+        let mut graphic_eq = self.graphic_eq.clone();
+        graphic_eq.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let sorted = ApoConfig {
+            graphic_eq,
+            ..self.clone()
+        };
+
+        let mut result = fit::fit_curve(|freq| sorted.response(freq));
+        if let Some(preamp) = self.preamp {
+            result.bands[0] =
+                preamp.clamp(-MAX_GAIN as f64, MAX_GAIN as f64) as f32;
+        }
+        result
+    }
+}
+
+/// Parses a config file and maps it onto the G6 bands
+pub fn import(content: &str) -> ApoImport {
+    let config = parse(content);
+    let fit = config.to_ten_band_eq();
+    ApoImport {
+        profile: Profile::from_ten_band_eq(fit.bands),
+        fit,
+        unsupported: config.unsupported,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+# AutoEq ParametricEQ.txt
+Preamp: -6.2 dB
+Filter 1: ON LSC Fc 105 Hz Gain 5.5 dB Q 0.71
+Filter 2: ON PK Fc 2000 Hz Gain -3.0 dB Q 1.41
+Filter 3: OFF PK Fc 4000 Hz Gain 9.0 dB Q 2.00
+Filter 4: ON HS Fc 10000 Hz Gain 2.0 dB
+Channel: L
+";
+
+    #[test]
+    fn parses_sample_config() {
+        let config = parse(SAMPLE);
+        assert_eq!(config.preamp, Some(-6.2));
+        assert_eq!(
+            config.filters,
+            [
+                Filter::LowShelf {
+                    freq: 105.0,
+                    gain: 5.5,
+                    q: 0.71
+                },
+                Filter::Peaking {
+                    freq: 2000.0,
+                    gain: -3.0,
+                    q: 1.41
+                },
+                Filter::HighShelf {
+                    freq: 10000.0,
+                    gain: 2.0,
+                    q: DEFAULT_SHELF_Q,
+                },
+            ]
+        );
+        assert_eq!(config.unsupported.len(), 1);
+        assert_eq!(config.unsupported[0].line, 7);
+    }
+
+    #[test]
+    fn preamp_lines_add_up() {
+        let config = parse("Preamp: -3 dB\nPreamp: -1.5 dB");
+        assert_eq!(config.preamp, Some(-4.5));
+    }
+
+    #[test]
+    fn parses_graphic_eq() {
+        let config = parse("GraphicEQ: 20 -2.1; 1000 0; 20000 3.5");
+        assert_eq!(
+            config.graphic_eq,
+            [(20.0, -2.1), (1000.0, 0.0), (20000.0, 3.5)]
+        );
+    }
+
+    #[test]
+    fn rejects_undefined_values() {
+        let config = parse(
+            "Filter 1: ON PK Fc 0 Hz Gain 3 dB Q 1\n\
+             Filter 2: ON PK Fc 1000 Hz Gain 3 dB Q 0\n\
+             Filter 3: ON PK Fc 1000 Hz Gain nan dB Q 1\n\
+             Filter 4: ON PK Fc 30000 Hz Gain 3 dB Q 1\n\
+             Preamp: inf dB\n\
+             GraphicEQ: 20 nan; 1000 0",
+        );
+        assert!(config.filters.is_empty());
+        assert_eq!(config.preamp, None);
+        assert!(config.graphic_eq.is_empty());
+        let lines: Vec<usize> =
+            config.unsupported.iter().map(|u| u.line).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn import_keeps_the_preamp_of_the_file() {
+        let import = import(SAMPLE);
+        assert_eq!(import.fit.bands[0], -6.2);
+        assert!(import.fit.bands.iter().all(|b| b.is_finite()));
+        assert!(import.fit.bands[1] > 3.0, "{:?}", import.fit.bands);
+    }
+}
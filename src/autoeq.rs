@@ -0,0 +1,318 @@
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, RwLock};
+use utoipa::ToSchema;
+
+use crate::autoeq_parse::{self, ParsedResult};
+
+/// Most headphones returned by a search
+pub const SEARCH_LIMIT: usize = 50;
+
+/// One AutoEq measurement of a headphone, fitted to the 10 G6 bands
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+pub struct HeadphoneResult {
+    pub tester: &'static str,
+    pub variant: Option<&'static str>,
+    /// The measurement rig, e.g. `GRAS RA0045`
+    pub test_device: Option<&'static str>,
+    pub preamp: f32,
+    pub ten_band_eq: [f32; 10],
+}
+
+// generated by build.rs:
+// pub static AUTOEQ_DB: phf::Map<&'static str, &'static [HeadphoneResult]>
+include!(concat!(env!("OUT_DIR"), "/autoeq_db.rs"));
+
+/// The built-in table, merged with or replaced by results loaded at runtime
+static DATABASE: LazyLock<RwLock<Database>> =
+    LazyLock::new(|| RwLock::new(Database::builtin()));
+
+/// [`HeadphoneResult`] that isn't necessarily compiled in
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Measurement {
+    pub tester: String,
+    pub variant: Option<String>,
+    /// The measurement rig, e.g. `GRAS RA0045`
+    pub test_device: Option<String>,
+    pub preamp: f32,
+    pub ten_band_eq: [f32; 10],
+}
+
+impl Measurement {
+    /// Same headphone variant, measured by the same tester on the same rig
+    fn same_source(&self, other: &Self) -> bool {
+        self.tester == other.tester
+            && self.variant == other.variant
+            && self.test_device == other.test_device
+    }
+}
+
+impl From<&HeadphoneResult> for Measurement {
+    fn from(result: &HeadphoneResult) -> Self {
+        Self {
+            tester: result.tester.to_string(),
+            variant: result.variant.map(str::to_string),
+            test_device: result.test_device.map(str::to_string),
+            preamp: result.preamp,
+            ten_band_eq: result.ten_band_eq,
+        }
+    }
+}
+
+impl From<ParsedResult> for Measurement {
+    fn from(result: ParsedResult) -> Self {
+        Self {
+            tester: result.tester,
+            variant: result.variant,
+            test_device: result.test_device,
+            preamp: result.preamp,
+            ten_band_eq: result.ten_band_eq,
+        }
+    }
+}
+
+/// A measurement whose bands are close to a reference
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct SimilarHeadphone {
+    pub name: String,
+    pub measurement: Measurement,
+    /// RMS difference of the 10 bands in dB
+    pub distance: f32,
+}
+
+/// Restricts measurements to a tester and / or rig
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeasurementFilter<'a> {
+    pub tester: Option<&'a str>,
+    pub test_device: Option<&'a str>,
+}
+
+impl MeasurementFilter<'_> {
+    pub fn matches(&self, measurement: &Measurement) -> bool {
+        self.tester.is_none_or(|t| t == measurement.tester)
+            && self
+                .test_device
+                .is_none_or(|d| Some(d) == measurement.test_device.as_deref())
+    }
+}
+
+/// A headphone and all of its measurements
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct Headphone {
+    pub name: String,
+    pub results: Vec<Measurement>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadMode {
+    /// Loaded measurements win over built-in ones from the same source
+    #[default]
+    Merge,
+    /// Only the loaded measurements are used
+    Replace,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct DatabaseInfo {
+    pub headphones: usize,
+    pub measurements: usize,
+    /// Compiled in measurements
+    pub builtin: usize,
+    /// Where runtime results were loaded from, `None` if only built-in
+    #[schema(value_type = Option<String>)]
+    pub source: Option<PathBuf>,
+    pub mode: Option<LoadMode>,
+}
+
+struct Database {
+    headphones: BTreeMap<String, Vec<Measurement>>,
+    source: Option<(PathBuf, LoadMode)>,
+}
+
+impl Database {
+    fn builtin() -> Self {
+        let headphones = AUTOEQ_DB
+            .entries()
+            .map(|(name, results)| {
+                (name.to_string(), results.iter().map(Into::into).collect())
+            })
+            .collect();
+        Self {
+            headphones,
+            source: None,
+        }
+    }
+
+    fn merge(&mut self, results: Vec<ParsedResult>) {
+        for result in results {
+            let name = result.name.clone();
+            let measurement = Measurement::from(result);
+            let measurements = self.headphones.entry(name).or_default();
+            match measurements
+                .iter_mut()
+                .find(|m| m.same_source(&measurement))
+            {
+                Some(existing) => *existing = measurement,
+                None => measurements.push(measurement),
+            }
+        }
+    }
+}
+
+/// Reads AutoEq results from
+/// - a checkout or its `results/` directory (`INDEX.md` + `FixedBandEQ.txt`s)
+/// - an `INDEX.md` inside such a directory
+/// - a snapshot file as written by `build.rs` (`.tsv`)
+pub fn read_results(path: &Path) -> Result<Vec<ParsedResult>, Box<dyn Error>> {
+    let results = if path.is_dir() {
+        let results_dir = if path.join("results/INDEX.md").exists() {
+            path.join("results")
+        } else {
+            path.to_path_buf()
+        };
+        autoeq_parse::parse_results_dir(&results_dir)?
+    } else if path.file_name().is_some_and(|name| name == "INDEX.md") {
+        autoeq_parse::parse_results_dir(
+            path.parent().unwrap_or(Path::new(".")),
+        )?
+    } else {
+        autoeq_parse::parse_snapshot(&std::fs::read_to_string(path)?)
+    };
+
+    if results.is_empty() {
+        return Err(Box::new(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("No AutoEq results found in {}", path.display()),
+        )));
+    }
+    Ok(results)
+}
+
+/// Loads AutoEq results at runtime, see [`read_results`].
+/// Replaces whatever was loaded before.
+pub fn load(
+    path: &Path,
+    mode: LoadMode,
+) -> Result<DatabaseInfo, Box<dyn Error>> {
+    let results = read_results(path)?;
+
+    let mut db = match mode {
+        LoadMode::Merge => Database::builtin(),
+        LoadMode::Replace => Database {
+            headphones: BTreeMap::new(),
+            source: None,
+        },
+    };
+    db.merge(results);
+    db.source = Some((path.to_path_buf(), mode));
+
+    *DATABASE.write().unwrap() = db;
+    Ok(info())
+}
+
+/// Goes back to the built-in table
+pub fn reset() -> DatabaseInfo {
+    *DATABASE.write().unwrap() = Database::builtin();
+    info()
+}
+
+pub fn info() -> DatabaseInfo {
+    let db = DATABASE.read().unwrap();
+    DatabaseInfo {
+        headphones: db.headphones.len(),
+        measurements: db.headphones.values().map(Vec::len).sum(),
+        builtin: AUTOEQ_DB.values().map(|results| results.len()).sum(),
+        source: db.source.as_ref().map(|(path, _)| path.clone()),
+        mode: db.source.as_ref().map(|(_, mode)| *mode),
+    }
+}
+
+/// Fuzzy search over the headphone names, best match first
+pub fn search(query: &str, limit: usize) -> Vec<Headphone> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let db = DATABASE.read().unwrap();
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut matches: Vec<(i64, &String)> = db
+        .headphones
+        .keys()
+        .filter_map(|name| {
+            matcher.fuzzy_match(name, query).map(|score| (score, name))
+        })
+        .collect();
+    // shorter names first on equal score, "HD 600" before "HD 600 (2020)"
+    matches.sort_unstable_by_key(|(score, name)| {
+        (Reverse(*score), name.len(), *name)
+    });
+
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, name)| Headphone {
+            name: name.clone(),
+            results: db.headphones[name].clone(),
+        })
+        .collect()
+}
+
+/// A headphone by its exact name
+pub fn find(name: &str) -> Option<Headphone> {
+    let db = DATABASE.read().unwrap();
+    db.headphones.get(name).map(|results| Headphone {
+        name: name.to_string(),
+        results: results.clone(),
+    })
+}
+
+/// Headphones whose correction is closest to `bands`, closest first.
+/// Each headphone is ranked by its closest matching measurement,
+/// `exclude` (usually the reference itself) is skipped.
+pub fn similar(
+    bands: &[f32; 10],
+    filter: MeasurementFilter,
+    exclude: Option<&str>,
+    limit: usize,
+) -> Vec<SimilarHeadphone> {
+    let distance = |other: &[f32; 10]| {
+        let sum: f32 = bands
+            .iter()
+            .zip(other)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum();
+        (sum / bands.len() as f32).sqrt()
+    };
+
+    let db = DATABASE.read().unwrap();
+    let mut matches: Vec<SimilarHeadphone> = db
+        .headphones
+        .iter()
+        .filter(|(name, _)| Some(name.as_str()) != exclude)
+        .filter_map(|(name, results)| {
+            results
+                .iter()
+                .filter(|m| filter.matches(m))
+                .map(|m| (m, distance(&m.ten_band_eq)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(measurement, distance)| SimilarHeadphone {
+                    name: name.clone(),
+                    measurement: measurement.clone(),
+                    distance,
+                })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        a.distance.total_cmp(&b.distance).then(a.name.cmp(&b.name))
+    });
+    matches.truncate(limit);
+    matches
+}
@@ -0,0 +1,199 @@
+//! Parsing of AutoEq results, shared by `build.rs` and the library.
+//! Only uses std, so `build.rs` can include it with `#[path]`.
+
+use std::path::Path;
+
+/// One measurement of a headphone, fitted to the 10 G6 bands
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedResult {
+    /// Headphone name without the variant, the key in the database
+    pub name: String,
+    pub tester: String,
+    pub variant: Option<String>,
+    pub test_device: Option<String>,
+    pub preamp: f32,
+    pub ten_band_eq: [f32; 10],
+}
+
+/// One line of `results/INDEX.md`
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexEntry {
+    /// Full name including the variant, e.g. `1MORE Aero (ANC Off)`
+    pub name: String,
+    pub variant: Option<String>,
+    pub tester: String,
+    pub test_device: Option<String>,
+    /// Directory of the result, relative to `results/`
+    pub result_link: String,
+}
+
+pub fn url_decode(s: &str) -> String {
+    let mut out = Vec::new();
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let Ok(byte) = u8::from_str_radix(
+                std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or(""),
+                16,
+            )
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// `1MORE Aero (ANC Off)` => `1MORE Aero`
+pub fn base_name(name: &str) -> &str {
+    name.split(" (").next().unwrap_or(name)
+}
+
+/// Parses a line like
+/// `- [1MORE Aero (ANC Off)](./HypetheSonics/GRAS%20RA0045%20in-ear/1MORE%20Aero%20(ANC%20Off)) by HypetheSonics on GRAS RA0045`
+pub fn parse_index_line(line: &str) -> Option<IndexEntry> {
+    // all lines of interest start with "- ["
+    let line = line.strip_prefix("- [")?;
+
+    let (name, link_part) = line.split_once("](")?;
+    let variant = name
+        .split(" (")
+        .nth(1)
+        .map(|s| s.trim_end_matches(')').to_string());
+
+    // "HypetheSonics on GRAS RA0045"
+    let tester_part = link_part.split(" by ").nth(1)?;
+    let mut tester_parts = tester_part.split(" on ");
+    let tester = tester_parts.next().unwrap_or("").trim();
+    if tester.is_empty() {
+        return None;
+    }
+    let test_device = tester_parts.next().map(|s| s.trim().to_string());
+
+    // some links contain literal brackets as part of the link,
+    // so the link ends at the last one
+    let end_index = link_part.rfind(')').unwrap_or(0);
+    let result_link = &link_part[..end_index];
+    let result_link = result_link.strip_prefix("./").unwrap_or(result_link);
+
+    Some(IndexEntry {
+        name: name.to_string(),
+        variant,
+        tester: tester.to_string(),
+        test_device,
+        result_link: url_decode(result_link),
+    })
+}
+
+/// Parses a `FixedBandEQ.txt`, returns the pre-amp and the 10 band gains
+pub fn parse_fixed_band_eq(content: &str) -> (f32, [f32; 10]) {
+    let mut preamp = 0.0;
+    let mut ten_band_eq = [0.0; 10];
+
+    for line in content.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.is_empty() {
+            continue;
+        }
+
+        if parts[0] == "Preamp:" && parts.len() >= 2 {
+            if let Ok(val) = parts[1].parse::<f32>() {
+                preamp = val;
+            }
+        } else if parts[0] == "Filter" && parts.len() >= 9 {
+            // Filter 1: ON PK Fc 31 Hz Gain 5.8 dB Q 1.41
+            let idx_str = parts[1].trim_end_matches(':');
+            if let Ok(idx) = idx_str.parse::<usize>()
+                && (1..=10).contains(&idx)
+                && let Ok(gain) = parts[8].parse::<f32>()
+            {
+                ten_band_eq[idx - 1] = gain;
+            }
+        }
+    }
+
+    (preamp, ten_band_eq)
+}
+
+/// Reads every result listed in `results/INDEX.md` that has a
+/// `FixedBandEQ.txt`. Results without one are skipped.
+pub fn parse_results_dir(
+    results_dir: &Path,
+) -> std::io::Result<Vec<ParsedResult>> {
+    let index = std::fs::read_to_string(results_dir.join("INDEX.md"))?;
+    let mut results = Vec::new();
+
+    for entry in index.lines().filter_map(parse_index_line) {
+        let fixed_band_path = results_dir
+            .join(&entry.result_link)
+            .join(format!("{} FixedBandEQ.txt", entry.name));
+        let Ok(content) = std::fs::read_to_string(&fixed_band_path) else {
+            continue;
+        };
+        let (preamp, ten_band_eq) = parse_fixed_band_eq(&content);
+
+        results.push(ParsedResult {
+            name: base_name(&entry.name).to_string(),
+            tester: entry.tester,
+            variant: entry.variant,
+            test_device: entry.test_device,
+            preamp,
+            ten_band_eq,
+        });
+    }
+
+    Ok(results)
+}
+
+/// Snapshot line layout, tab separated, empty fields for missing values:
+/// `name tester variant test_device preamp gain1 .. gain10`
+pub fn parse_snapshot(content: &str) -> Vec<ParsedResult> {
+    content
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 15 {
+                return None;
+            }
+            let optional = |s: &str| (!s.is_empty()).then(|| s.to_string());
+            let mut ten_band_eq = [0.0; 10];
+            for (gain, field) in ten_band_eq.iter_mut().zip(&fields[5..]) {
+                *gain = field.parse().ok()?;
+            }
+            Some(ParsedResult {
+                name: fields[0].to_string(),
+                tester: fields[1].to_string(),
+                variant: optional(fields[2]),
+                test_device: optional(fields[3]),
+                preamp: fields[4].parse().ok()?,
+                ten_band_eq,
+            })
+        })
+        .collect()
+}
+
+pub fn write_snapshot(results: &[ParsedResult]) -> String {
+    let mut out = String::from(
+        "# name\ttester\tvariant\ttest_device\tpreamp\t31Hz..16kHz\n",
+    );
+    for result in results {
+        let gains: Vec<String> =
+            result.ten_band_eq.iter().map(|g| g.to_string()).collect();
+        out.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            result.name,
+            result.tester,
+            result.variant.as_deref().unwrap_or(""),
+            result.test_device.as_deref().unwrap_or(""),
+            result.preamp,
+            gains.join("\t")
+        ));
+    }
+    out
+}
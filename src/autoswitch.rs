@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::api::{self, AppState, PulseStream, StreamDirection};
+use crate::profile::{Layer, OverrideId};
+use crate::write_atomic;
+
+/// How often the running streams are checked against the rules
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Applies a profile while a matching application
+/// plays to or records from the G6
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct AppRule {
+    /// Matched against `application.name`, ignoring case
+    #[serde(default)]
+    pub application: Option<String>,
+    /// Matched against `application.process.binary`, ignoring case
+    #[serde(default)]
+    pub binary: Option<String>,
+    /// Only match playback or capture streams, both if omitted
+    #[serde(default)]
+    pub direction: Option<StreamDirection>,
+    /// Name of the profile to apply
+    pub profile: String,
+    /// Layers to take from the profile, all of them if omitted
+    #[serde(default)]
+    pub layers: Option<Vec<Layer>>,
+}
+
+impl AppRule {
+    pub fn matches(&self, stream: &PulseStream) -> bool {
+        let equals = |pattern: &Option<String>, value: &Option<String>| match (
+            pattern, value,
+        ) {
+            (None, _) => true,
+            (Some(pattern), Some(value)) => pattern.eq_ignore_ascii_case(value),
+            (Some(_), None) => false,
+        };
+
+        self.direction.is_none_or(|d| d == stream.direction)
+            && equals(&self.application, &stream.application_name)
+            && equals(&self.binary, &stream.binary)
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.application.is_none() && self.binary.is_none() {
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Rule for profile {} needs an application or a binary",
+                    self.profile
+                ),
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The rule currently in effect and its profile to revert once it ends
+struct EngagedRule {
+    rule: AppRule,
+    applied: OverrideId,
+}
+
+/// Rules for automatic profile switching.
+/// The first rule with a matching stream wins.
+#[derive(Default)]
+pub struct AutoSwitch {
+    pub rules: Vec<AppRule>,
+    engaged: Option<EngagedRule>,
+}
+
+impl AutoSwitch {
+    /// Reads the rules, a missing file means no rules
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        let rules: Vec<AppRule> = serde_json::from_str(&content)?;
+        for rule in &rules {
+            rule.validate()?;
+        }
+        Ok(Self {
+            rules,
+            engaged: None,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_vec_pretty(&self.rules)?;
+        write_atomic(path, &json)
+    }
+
+    /// The rule whose profile is applied right now
+    pub fn engaged_rule(&self) -> Option<&AppRule> {
+        self.engaged.as_ref().map(|e| &e.rule)
+    }
+}
+
+/// The rules live next to the profile directory, not inside it,
+/// so they don't show up as a profile
+pub fn rules_path(profile_path: &Path) -> PathBuf {
+    profile_path
+        .parent()
+        .unwrap_or(profile_path)
+        .join("app_rules.json")
+}
+
+/// Starts the task that watches the G6 streams and switches profiles
+pub fn spawn_watcher(state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            check_streams(&state).await;
+        }
+    })
+}
+
+async fn check_streams(state: &AppState) {
+    let rules = {
+        let auto = state.autoswitch.lock().await;
+        if auto.rules.is_empty() && auto.engaged.is_none() {
+            return;
+        }
+        auto.rules.clone()
+    };
+
+    let streams = tokio::task::spawn_blocking(api::list_g6_streams)
+        .await
+        .unwrap_or_default();
+    let wanted = rules
+        .into_iter()
+        .find(|rule| streams.iter().any(|stream| rule.matches(stream)));
+
+    let mut auto = state.autoswitch.lock().await;
+    if auto.engaged_rule() == wanted.as_ref() {
+        return;
+    }
+
+    // takes over from a profile switch that is still ramping
+    state.ramp.start();
+    let mut device = state.device.lock().await;
+    if state.abx.lock().await.is_some() {
+        // the session owns the EQ, switching resumes once it's stopped
+        return;
+    }
+
+    if let Some(engaged) = auto.engaged.take() {
+        info!(
+            "Stream for profile {} is gone, reverting",
+            engaged.rule.profile
+        );
+        if let Err(e) = device.revert_temporary(engaged.applied) {
+            warn!("Failed to revert automatic profile: {}", e);
+        }
+    }
+
+    if let Some(rule) = wanted {
+        info!("Matching stream found, applying profile {}", rule.profile);
+        match device.apply_temporarily(&rule.profile, rule.layers.as_deref()) {
+            Ok(applied) => auto.engaged = Some(EngagedRule { rule, applied }),
+            Err(e) => {
+                warn!("Failed to apply profile {}: {}", rule.profile, e);
+            }
+        }
+    }
+}
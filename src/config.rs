@@ -0,0 +1,360 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{LazyLock, OnceLock, RwLock};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{Registry, reload};
+use utoipa::ToSchema;
+
+use crate::autoeq::{self, LoadMode};
+use crate::ramp;
+use crate::write_atomic;
+
+/// Prefix of the environment variables that override the config file,
+/// e.g. `LINUXBLASTER_PORT=3312`
+pub const ENV_PREFIX: &str = "LINUXBLASTER_";
+
+/// Settings that can be set from the environment and with `--key value`,
+/// except for `autostart`, which is a flag
+const SETTINGS: [&str; 8] = [
+    "port",
+    "autostart",
+    "log_level",
+    "alsa_card",
+    "pulse_prefix",
+    "autoeq_path",
+    "autoeq_mode",
+    "transition_ms",
+];
+
+static CONFIG: LazyLock<RwLock<Config>> =
+    LazyLock::new(|| RwLock::new(Config::default()));
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+static LOG_LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> =
+    OnceLock::new();
+
+/// Application settings from `$XDG_CONFIG_HOME/linuxblaster/config.toml`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Port of the web server on 127.0.0.1
+    pub port: u16,
+    /// Start minimized with the desktop session
+    pub autostart: bool,
+    /// `trace`, `debug`, `info`, `warn`, `error` or `off`
+    pub log_level: String,
+    /// ALSA card name for amixer
+    pub alsa_card: String,
+    /// Start of the PulseAudio/PipeWire sink and source names
+    pub pulse_prefix: String,
+    /// AutoEq results to load at startup, see [`autoeq::read_results`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub autoeq_path: Option<PathBuf>,
+    /// Whether `autoeq_path` is merged with or replaces the built-in table
+    pub autoeq_mode: LoadMode,
+    /// How long switching profiles ramps the EQ in ms, 0 switches at once
+    pub transition_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: 3311,
+            autostart: true,
+            log_level: "info".to_string(),
+            alsa_card: "G6".to_string(),
+            pulse_prefix: "Sound_BlasterX_G6".to_string(),
+            autoeq_path: None,
+            autoeq_mode: LoadMode::Merge,
+            transition_ms: 0,
+        }
+    }
+}
+
+/// The settings that can change while running, all optional.
+/// Port changes need a restart, so they are rejected.
+#[derive(Deserialize, Clone, Debug, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigUpdate {
+    pub autostart: Option<bool>,
+    pub log_level: Option<String>,
+    pub alsa_card: Option<String>,
+    pub pulse_prefix: Option<String>,
+    pub transition_ms: Option<u64>,
+}
+
+fn invalid_input(message: String) -> Box<dyn Error> {
+    Box::new(std::io::Error::new(ErrorKind::InvalidInput, message))
+}
+
+impl Config {
+    /// Reads the config file, a missing file means the defaults
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        let config: Self = toml::from_str(&content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let content = toml::to_string_pretty(self)?;
+        write_atomic(path, content.as_bytes())
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.port == 0 {
+            return Err(invalid_input("Port must not be 0".to_string()));
+        }
+        self.level_filter()?;
+        if self.alsa_card.trim().is_empty() {
+            return Err(invalid_input("ALSA card must not be empty".into()));
+        }
+        if self.pulse_prefix.trim().is_empty() {
+            return Err(invalid_input("Pulse prefix must not be empty".into()));
+        }
+        if self.transition_ms > ramp::MAX_RAMP.as_millis() as u64 {
+            return Err(invalid_input(format!(
+                "Transition must not be longer than {} ms",
+                ramp::MAX_RAMP.as_millis()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn level_filter(&self) -> Result<LevelFilter, Box<dyn Error>> {
+        LevelFilter::from_str(&self.log_level).map_err(|_| {
+            invalid_input(format!("Invalid log level {:?}", self.log_level))
+        })
+    }
+
+    /// Applies `LINUXBLASTER_*` environment variables.
+    /// Unknown ones are skipped with a warning, logging isn't set up yet.
+    pub fn apply_env(&mut self) -> Result<(), Box<dyn Error>> {
+        // variables of other programs don't have to be UTF-8
+        for (key, value) in env::vars_os() {
+            let Some(key) =
+                key.to_str().and_then(|k| k.strip_prefix(ENV_PREFIX))
+            else {
+                continue;
+            };
+            if key == "CONFIG" {
+                continue;
+            }
+            let setting = key.to_ascii_lowercase();
+            if !SETTINGS.contains(&setting.as_str()) {
+                eprintln!("Ignoring unknown setting {}{}", ENV_PREFIX, key);
+                continue;
+            }
+            let value = value.to_str().ok_or_else(|| {
+                invalid_input(format!("{}{} is not UTF-8", ENV_PREFIX, key))
+            })?;
+            self.set(&setting, value)?;
+        }
+        Ok(())
+    }
+
+    /// Applies `--port 3312`, `--log-level=debug`, `--no-autostart` etc.
+    /// Arguments that aren't settings are skipped.
+    pub fn apply_args(
+        &mut self,
+        args: &[String],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                continue;
+            };
+            let (key, inline) = match flag.split_once('=') {
+                Some((key, value)) => (key, Some(value.to_string())),
+                None => (flag, None),
+            };
+            let key = key.replace('-', "_");
+            match key.as_str() {
+                "autostart" => self.autostart = true,
+                "no_autostart" => self.autostart = false,
+                key if SETTINGS.contains(&key) => {
+                    let value =
+                        inline.or_else(|| args.next().cloned()).ok_or_else(
+                            || invalid_input(format!("{} needs a value", arg)),
+                        )?;
+                    self.set(key, &value)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        let invalid = || invalid_input(format!("Invalid {}: {:?}", key, value));
+        match key {
+            "port" => self.port = value.parse().map_err(|_| invalid())?,
+            "autostart" => {
+                self.autostart = match value {
+                    "1" | "true" | "yes" => true,
+                    "0" | "false" | "no" => false,
+                    _ => return Err(invalid()),
+                }
+            }
+            "log_level" => self.log_level = value.to_string(),
+            "alsa_card" => self.alsa_card = value.to_string(),
+            "pulse_prefix" => self.pulse_prefix = value.to_string(),
+            "autoeq_path" => self.autoeq_path = Some(PathBuf::from(value)),
+            "autoeq_mode" => {
+                self.autoeq_mode = match value {
+                    "merge" => LoadMode::Merge,
+                    "replace" => LoadMode::Replace,
+                    _ => return Err(invalid()),
+                }
+            }
+            "transition_ms" => {
+                self.transition_ms = value.parse().map_err(|_| invalid())?
+            }
+            _ => return Err(invalid_input(format!("Unknown setting {}", key))),
+        }
+        Ok(())
+    }
+
+    fn apply_update(&mut self, update: &ConfigUpdate) {
+        if let Some(autostart) = update.autostart {
+            self.autostart = autostart;
+        }
+        if let Some(log_level) = &update.log_level {
+            self.log_level = log_level.clone();
+        }
+        if let Some(alsa_card) = &update.alsa_card {
+            self.alsa_card = alsa_card.clone();
+        }
+        if let Some(pulse_prefix) = &update.pulse_prefix {
+            self.pulse_prefix = pulse_prefix.clone();
+        }
+        if let Some(transition_ms) = update.transition_ms {
+            self.transition_ms = transition_ms;
+        }
+    }
+}
+
+/// `--config`, `LINUXBLASTER_CONFIG` or the XDG default
+fn find_config_path(args: &[String]) -> PathBuf {
+    let flag = args
+        .iter()
+        .position(|a| a == "--config")
+        .and_then(|i| args.get(i + 1));
+    let inline = args.iter().find_map(|a| a.strip_prefix("--config="));
+    if let Some(path) = flag.map(String::as_str).or(inline) {
+        return PathBuf::from(path);
+    }
+    if let Ok(path) = env::var(format!("{}CONFIG", ENV_PREFIX)) {
+        return PathBuf::from(path);
+    }
+    PathBuf::from(format!(
+        "{}/linuxblaster/config.toml",
+        env::var("XDG_CONFIG_HOME").unwrap_or_else(|_| format!(
+            "{}/.config",
+            env::var("HOME").expect("HOME is not set")
+        )),
+    ))
+}
+
+/// Loads the config file, then applies the environment and the arguments.
+/// Later sources win.
+pub fn init(args: &[String]) -> Result<Config, Box<dyn Error>> {
+    let path = find_config_path(args);
+    let mut config = Config::load(&path).map_err(|e| {
+        invalid_input(format!("Failed to load {}: {}", path.display(), e))
+    })?;
+    config.apply_env()?;
+    config.apply_args(args)?;
+    config.validate()?;
+
+    let _ = CONFIG_PATH.set(path);
+    *CONFIG.write().unwrap() = config.clone();
+    Ok(config)
+}
+
+/// The settings in effect
+pub fn get() -> Config {
+    CONFIG.read().unwrap().clone()
+}
+
+pub fn config_path() -> Option<&'static Path> {
+    CONFIG_PATH.get().map(PathBuf::as_path)
+}
+
+/// Sets up logging with a level that can be changed at runtime
+pub fn init_logging(config: &Config) {
+    let (filter, handle) =
+        reload::Layer::new(config.level_filter().unwrap_or(LevelFilter::INFO));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    let _ = LOG_LEVEL.set(handle);
+}
+
+/// Changes settings while running and stores them in the config file.
+/// Only the changed settings are written, values that came from
+/// the environment or arguments stay out of the file.
+pub fn update(update: &ConfigUpdate) -> Result<Config, Box<dyn Error>> {
+    let mut config = get();
+    config.apply_update(update);
+    config.validate()?;
+
+    if let Some(path) = config_path() {
+        let mut stored = Config::load(path)?;
+        stored.apply_update(update);
+        stored.save(path)?;
+    }
+
+    let previous =
+        std::mem::replace(&mut *CONFIG.write().unwrap(), config.clone());
+    if previous.log_level != config.log_level
+        && let Some(handle) = LOG_LEVEL.get()
+    {
+        handle.reload(config.level_filter()?)?;
+    }
+    if previous.autostart != config.autostart {
+        set_autostart(config.autostart);
+    }
+
+    Ok(config)
+}
+
+/// Creates or removes the desktop autostart entry
+pub fn set_autostart(enabled: bool) {
+    let is_flatpak = env::var("FLATPAK_ID").is_ok();
+    let desktop_file =
+        "~/.config/autostart/cc.dreamzone.SoundBlasterG6X.desktop";
+
+    let script = if enabled {
+        format!(
+            "mkdir -p ~/.config/autostart && echo '[Desktop Entry]\nType=Application\nName=Sound Blaster G6X Controller\nExec={} --minimized\nIcon=cc.dreamzone.SoundBlasterG6X\nTerminal=false\nStartupNotify=false\n' > {}",
+            if is_flatpak {
+                "flatpak run cc.dreamzone.SoundBlasterG6X"
+            } else {
+                "soundblaster-g6x"
+            },
+            desktop_file
+        )
+    } else {
+        format!("rm -f {}", desktop_file)
+    };
+
+    if is_flatpak {
+        let _ = std::process::Command::new("flatpak-spawn")
+            .args(["--host", "bash", "-c", &script])
+            .output();
+    } else {
+        let _ = std::process::Command::new("bash")
+            .args(["-c", &script])
+            .output();
+    }
+}
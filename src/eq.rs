@@ -0,0 +1,113 @@
+//! Frequency response model of the G6 10 band EQ
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::ISO_BANDS;
+
+/// Q of the G6 bands
+pub const BAND_Q: f64 = 1.41;
+
+/// Range of the Pre-Amp and the bands in dB
+pub const MAX_GAIN: f32 = 12.0;
+
+/// Audible range the responses are evaluated over
+pub const MIN_FREQ: f64 = 20.0;
+pub const MAX_FREQ: f64 = 20000.0;
+
+/// Default number of points of a [`Response`]
+pub const RESPONSE_POINTS: usize = 128;
+
+/// Most points of a [`Response`]
+pub const MAX_RESPONSE_POINTS: usize = 2048;
+
+/// Frequency response of Pre-Amp and bands
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Response {
+    pub bands: [f32; 11],
+    /// Frequency in Hz and gain in dB, log spaced over the audible range
+    pub points: Vec<(f64, f64)>,
+    /// Highest gain of the curve in dB and where it is,
+    /// anything above 0 dB can clip
+    pub peak: f64,
+    pub peak_freq: f64,
+}
+
+/// Response in dB of a single band at `freq`
+pub fn peaking_response(freq: f64, center_freq: f64, gain: f64, q: f64) -> f64 {
+    let bandwidth = center_freq / q;
+    let diff = (freq - center_freq).abs();
+    let falloff = 1.0 / (1.0 + (diff / (bandwidth * 0.5)).powf(2.0));
+    gain * falloff
+}
+
+/// Response in dB of Pre-Amp and bands,
+/// in the layout of [`crate::BlasterXG6::get_ten_band_eq`]
+pub fn ten_band_response(bands: &[f32; 11], freq: f64) -> f64 {
+    bands[0] as f64 + bands_response(&bands[1..], freq)
+}
+
+/// Response in dB of the bands alone, without the Pre-Amp
+pub fn bands_response(gains: &[f32], freq: f64) -> f64 {
+    ISO_BANDS
+        .iter()
+        .zip(gains)
+        .map(|(center, gain)| {
+            peaking_response(freq, *center, *gain as f64, BAND_Q)
+        })
+        .sum()
+}
+
+/// `points` frequencies from `from` to `to`, evenly spaced on a log scale
+pub fn log_spaced(points: usize, from: f64, to: f64) -> Vec<f64> {
+    match points {
+        0 => Vec::new(),
+        1 => vec![from],
+        _ => (0..points)
+            .map(|i| {
+                let t = i as f64 / (points - 1) as f64;
+                from * (to / from).powf(t)
+            })
+            .collect(),
+    }
+}
+
+/// Response of `bands` at `points` log spaced frequencies,
+/// in the layout of [`crate::BlasterXG6::get_ten_band_eq`]
+pub fn response(bands: &[f32; 11], points: usize) -> Response {
+    let points: Vec<(f64, f64)> =
+        log_spaced(points.clamp(2, MAX_RESPONSE_POINTS), MIN_FREQ, MAX_FREQ)
+            .into_iter()
+            .map(|freq| (freq, ten_band_response(bands, freq)))
+            .collect();
+    let (peak_freq, peak) = points
+        .iter()
+        .cloned()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((MIN_FREQ, bands[0] as f64));
+    Response {
+        bands: *bands,
+        points,
+        peak,
+        peak_freq,
+    }
+}
+
+/// Gain at `freq` of a curve given as sorted frequency / gain points,
+/// linear between the points on a log scale and flat beyond the ends
+pub fn interpolate(points: &[(f64, f64)], freq: f64) -> f64 {
+    let Some(first) = points.first() else {
+        return 0.0;
+    };
+    if freq <= first.0 {
+        return first.1;
+    }
+    for pair in points.windows(2) {
+        let ((f0, g0), (f1, g1)) = (pair[0], pair[1]);
+        if freq <= f1 {
+            let t = (freq.ln() - f0.ln()) / (f1.ln() - f0.ln());
+            return g0 + t * (g1 - g0);
+        }
+    }
+    points.last().map_or(0.0, |last| last.1)
+}
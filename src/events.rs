@@ -0,0 +1,205 @@
+//! State changes pushed to clients, see `/api/events`.
+//! Every mutation publishes to one broadcast channel, each client
+//! subscribes to it. Clients that fall behind get a `resync` and
+//! reload the full state.
+
+use futures_util::Stream;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::ToSchema;
+
+use crate::api::{self, AppState, MixerResponse};
+use crate::loudness::{self, Loudness};
+use crate::preamp::AutoPreAmp;
+use crate::profile::{ActiveLayer, Layer};
+use crate::tone::ToneControls;
+use crate::{FEATURES, FeatureType, PRODUCT_ID, VENDOR_ID};
+
+/// Events a client may fall behind by before it has to resync
+pub const CAPACITY: usize = 256;
+
+/// How often mixer and USB connection are checked while clients listen,
+/// and the playback volume while loudness compensation is on
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A feature got a new value
+    Feature { name: String, value: FeatureType },
+    /// Auto Pre-Amp, loudness compensation or tone controls changed
+    Eq {
+        auto_preamp: AutoPreAmp,
+        loudness: Loudness,
+        tone: Option<ToneControls>,
+    },
+    /// Levels of a mixer control, only the changed ones are set
+    Mixer {
+        name: String,
+        levels: MixerResponse,
+    },
+    /// Profiles were applied or a layer got modified
+    Profile {
+        active_layers: BTreeMap<Layer, ActiveLayer>,
+    },
+    /// A profile file was written or removed by another program,
+    /// `error` if it doesn't load anymore
+    ProfileFile {
+        name: String,
+        removed: bool,
+        error: Option<String>,
+    },
+    /// The G6 was plugged in or out
+    Device { connected: bool },
+    /// Events were dropped, the full state has to be reloaded
+    Resync,
+}
+
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<Event>,
+    connected: Arc<AtomicBool>,
+    /// EQ features are held back, see [`Events::set_blind`]
+    blind: Arc<AtomicBool>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            connected: Arc::new(AtomicBool::new(true)),
+            blind: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Events {
+    /// Sends an event to all clients, dropped if there are none
+    pub fn publish(&self, event: Event) {
+        if let Event::Feature { name, .. } = &event
+            && self.blind.load(Ordering::SeqCst)
+            && FEATURES
+                .iter()
+                .any(|f| f.name == name.as_str() && Layer::of(f) == Layer::Eq)
+        {
+            return;
+        }
+        let _ = self.sender.send(event);
+    }
+
+    /// Holds back changes of EQ features while an ABX session runs,
+    /// they would tell A and B apart. Clients resync once it's over.
+    pub fn set_blind(&self, blind: bool) {
+        if self.blind.swap(blind, Ordering::SeqCst) && !blind {
+            self.publish(Event::Resync);
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    fn set_connected(&self, connected: bool) {
+        if self.connected.swap(connected, Ordering::SeqCst) != connected {
+            self.publish(Event::Device { connected });
+        }
+    }
+
+    /// Events from now on, starting with the connection state
+    pub fn stream(&self) -> impl Stream<Item = Event> + use<> {
+        let first = Event::Device {
+            connected: self.is_connected(),
+        };
+        futures_util::stream::unfold(
+            (Some(first), self.sender.subscribe()),
+            |(first, mut receiver)| async move {
+                if let Some(event) = first {
+                    return Some((event, (None, receiver)));
+                }
+                let event = match receiver.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => Event::Resync,
+                    Err(RecvError::Closed) => return None,
+                };
+                Some((event, (None, receiver)))
+            },
+        )
+    }
+}
+
+/// Whether a G6 is on the USB bus, from sysfs
+pub fn device_present() -> bool {
+    let read_id = |dir: &Path, file: &str| {
+        std::fs::read_to_string(dir.join(file))
+            .ok()
+            .and_then(|id| u16::from_str_radix(id.trim(), 16).ok())
+    };
+    std::fs::read_dir("/sys/bus/usb/devices")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .any(|dir| {
+            read_id(&dir, "idVendor") == Some(VENDOR_ID)
+                && read_id(&dir, "idProduct") == Some(PRODUCT_ID)
+        })
+}
+
+/// Starts the task that publishes mixer changes made elsewhere,
+/// e.g. in pavucontrol, and the G6 being plugged in or out.
+/// The same poll feeds the volume to [`loudness::follow_volume`].
+/// Nothing is polled while no client listens and loudness is off.
+pub fn spawn_watcher(state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        let mut mixer: Option<HashMap<String, MixerResponse>> = None;
+        loop {
+            interval.tick().await;
+            let loudness = state.device.lock().await.loudness.settings.enabled;
+            if state.events.sender.receiver_count() == 0 {
+                // the next client gets the full state anyway
+                mixer = None;
+                if loudness
+                    && let Ok(Some(volume)) =
+                        tokio::task::spawn_blocking(api::read_speaker_volume)
+                            .await
+                {
+                    loudness::follow_volume(&state, volume).await;
+                }
+                continue;
+            }
+
+            let polled = tokio::task::spawn_blocking(|| {
+                (device_present(), api::read_mixer())
+            })
+            .await;
+            let Ok((present, current)) = polled else {
+                continue;
+            };
+
+            state.events.set_connected(present);
+            if let Some(previous) = &mixer {
+                for (name, levels) in &current {
+                    if previous.get(name) != Some(levels) {
+                        state.events.publish(Event::Mixer {
+                            name: name.clone(),
+                            levels: levels.clone(),
+                        });
+                    }
+                }
+            }
+            if loudness
+                && let Some(volume) =
+                    current.get("Speaker").and_then(|s| s.playback_vol)
+            {
+                loudness::follow_volume(&state, volume).await;
+            }
+            mixer = Some(current);
+        }
+    })
+}
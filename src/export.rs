@@ -0,0 +1,101 @@
+//! Exports the EQ for use without the G6
+
+use serde_json::json;
+
+use crate::ISO_BANDS;
+use crate::eq::BAND_Q;
+
+/// PipeWire `filter-chain` config, e.g. for
+/// `~/.config/pipewire/pipewire.conf.d/g6-eq.conf`.
+/// The Pre-Amp is a high shelf at 0 Hz, i.e. a plain gain.
+pub fn pipewire_filter_chain(bands: &[f32; 11]) -> String {
+    let mut nodes = vec![format!(
+        "                    {{ type = builtin name = preamp label = bq_highshelf control = {{ \"Freq\" = 0 \"Q\" = 1.0 \"Gain\" = {:.1} }} }}",
+        bands[0]
+    )];
+    let mut links = Vec::new();
+    let mut previous = "preamp".to_string();
+    for (idx, (freq, gain)) in ISO_BANDS.iter().zip(&bands[1..]).enumerate() {
+        let name = format!("band{}", idx + 1);
+        nodes.push(format!(
+            "                    {{ type = builtin name = {} label = bq_peaking control = {{ \"Freq\" = {} \"Q\" = {} \"Gain\" = {:.1} }} }}",
+            name, freq, BAND_Q, gain
+        ));
+        links.push(format!(
+            "                    {{ output = \"{}:Out\" input = \"{}:In\" }}",
+            previous, name
+        ));
+        previous = name;
+    }
+
+    format!(
+        r#"# Sound Blaster G6 EQ
+context.modules = [
+    {{ name = libpipewire-module-filter-chain
+        args = {{
+            node.description = "G6 Equalizer"
+            media.name       = "G6 Equalizer"
+            filter.graph = {{
+                nodes = [
+{}
+                ]
+                links = [
+{}
+                ]
+            }}
+            audio.channels = 2
+            audio.position = [ FL FR ]
+            capture.props = {{
+                node.name   = "effect_input.g6_eq"
+                media.class = Audio/Sink
+            }}
+            playback.props = {{
+                node.name    = "effect_output.g6_eq"
+                node.passive = true
+            }}
+        }}
+    }}
+]
+"#,
+        nodes.join("\n"),
+        links.join("\n")
+    )
+}
+
+/// EasyEffects output preset with a 10 band equalizer,
+/// for `~/.config/easyeffects/output/`
+pub fn easyeffects_preset(bands: &[f32; 11]) -> serde_json::Value {
+    let mut channel = serde_json::Map::new();
+    for (idx, (freq, gain)) in ISO_BANDS.iter().zip(&bands[1..]).enumerate() {
+        channel.insert(
+            format!("band{}", idx),
+            json!({
+                "frequency": freq,
+                "gain": gain,
+                "mode": "RLC (BT)",
+                "mute": false,
+                "q": BAND_Q,
+                "slope": "x1",
+                "solo": false,
+                "type": "Bell",
+            }),
+        );
+    }
+
+    json!({
+        "output": {
+            "blocklist": [],
+            "plugins_order": ["equalizer#0"],
+            "equalizer#0": {
+                "bypass": false,
+                "input-gain": bands[0],
+                "output-gain": 0.0,
+                "mode": "IIR",
+                "num-bands": ISO_BANDS.len(),
+                "split-channels": false,
+                "left": channel.clone(),
+                "right": channel,
+            },
+        },
+    })
+}
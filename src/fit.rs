@@ -0,0 +1,344 @@
+//! Fits arbitrary EQ curves onto the 10 fixed G6 bands
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::io::ErrorKind;
+use utoipa::ToSchema;
+
+use crate::ISO_BANDS;
+use crate::eq::{self, BAND_Q, MAX_FREQ, MAX_GAIN, MIN_FREQ};
+
+/// Sample rate the filters of parametric EQs are evaluated at
+pub const SAMPLE_RATE: f64 = 48000.0;
+
+/// Number of log spaced frequencies the fit is evaluated at
+pub const FIT_POINTS: usize = 240;
+
+/// A biquad filter of a parametric EQ
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Filter {
+    Peaking { freq: f64, gain: f64, q: f64 },
+    LowShelf { freq: f64, gain: f64, q: f64 },
+    HighShelf { freq: f64, gain: f64, q: f64 },
+}
+
+impl Filter {
+    /// Center or corner frequency, gain and Q
+    fn params(&self) -> (f64, f64, f64) {
+        match *self {
+            Filter::Peaking { freq, gain, q }
+            | Filter::LowShelf { freq, gain, q }
+            | Filter::HighShelf { freq, gain, q } => (freq, gain, q),
+        }
+    }
+
+    /// Rejects filters whose response isn't defined,
+    /// they'd turn every band into NaN
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let (freq, gain, q) = self.params();
+        let message =
+            if !(freq.is_finite() && 0.0 < freq && freq < SAMPLE_RATE / 2.0) {
+                format!(
+                    "Filter frequency must be between 0 and {} Hz, got {}",
+                    SAMPLE_RATE / 2.0,
+                    freq
+                )
+            } else if !(q.is_finite() && q > 0.0) {
+                format!("Filter Q must be above 0, got {}", q)
+            } else if !gain.is_finite() {
+                format!("Filter gain must be a number, got {}", gain)
+            } else {
+                return Ok(());
+            };
+        Err(Box::new(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            message,
+        )))
+    }
+
+    /// Response in dB at `freq`, RBJ Audio EQ Cookbook biquads
+    pub fn response(&self, freq: f64) -> f64 {
+        let (f0, gain, q) = self.params();
+        let a = 10f64.powf(gain / 40.0);
+        let w0 = 2.0 * PI * f0 / SAMPLE_RATE;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let (b, a) = match self {
+            Filter::Peaking { .. } => (
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            ),
+            Filter::LowShelf { .. } => (
+                [
+                    a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+                ],
+                [
+                    (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+                ],
+            ),
+            Filter::HighShelf { .. } => (
+                [
+                    a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+                ],
+                [
+                    (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+                ],
+            ),
+        };
+
+        let w = 2.0 * PI * freq / SAMPLE_RATE;
+        let magnitude = |c: [f64; 3]| {
+            let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+            let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+            (re * re + im * im).sqrt()
+        };
+        20.0 * (magnitude(b) / magnitude(a)).log10()
+    }
+}
+
+/// Combined response in dB of a list of filters
+pub fn filters_response(filters: &[Filter], freq: f64) -> f64 {
+    filters.iter().map(|f| f.response(freq)).sum()
+}
+
+/// Best approximation of a curve with the G6 bands
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct FitResult {
+    /// Pre-Amp and bands, in the layout of
+    /// [`crate::BlasterXG6::get_ten_band_eq`]
+    pub bands: [f32; 11],
+    /// RMS difference between the target and the fitted bands in dB
+    pub rms_error: f64,
+    /// Largest difference in dB and where it is
+    pub max_error: f64,
+    pub max_error_freq: f64,
+}
+
+/// Fits the bands to a target response given in dB.
+/// Least squares over [`FIT_POINTS`] log spaced frequencies,
+/// bands that would exceed ±12 dB are pinned there and the rest refitted.
+/// The Pre-Amp takes the peak of the fitted curve down to 0 dB.
+pub fn fit_curve(target: impl Fn(f64) -> f64) -> FitResult {
+    let freqs = eq::log_spaced(FIT_POINTS, MIN_FREQ, MAX_FREQ);
+    // a target that isn't defined somewhere must not spoil the other points
+    let targets: Vec<f64> = freqs
+        .iter()
+        .map(|f| target(*f))
+        .map(|t| if t.is_finite() { t } else { 0.0 })
+        .collect();
+    // response of each band at 1 dB, the model is linear in the gain
+    let basis: Vec<[f64; 10]> = freqs
+        .iter()
+        .map(|f| {
+            let mut row = [0.0; 10];
+            for (value, center) in row.iter_mut().zip(ISO_BANDS) {
+                *value = eq::peaking_response(*f, center, 1.0, BAND_Q);
+            }
+            row
+        })
+        .collect();
+
+    let mut gains = [0.0f64; 10];
+    let mut pinned = [false; 10];
+    loop {
+        let free: Vec<usize> = (0..10).filter(|i| !pinned[*i]).collect();
+        if free.is_empty() {
+            break;
+        }
+
+        // normal equations over the free bands,
+        // with the pinned bands taken out of the target
+        let n = free.len();
+        let mut ata = vec![vec![0.0; n]; n];
+        let mut atb = vec![0.0; n];
+        for (row, target) in basis.iter().zip(&targets) {
+            let pinned_part: f64 = (0..10)
+                .filter(|i| pinned[*i])
+                .map(|i| row[i] * gains[i])
+                .sum();
+            let rest = target - pinned_part;
+            for (a, &i) in free.iter().enumerate() {
+                atb[a] += row[i] * rest;
+                for (b, &j) in free.iter().enumerate() {
+                    ata[a][b] += row[i] * row[j];
+                }
+            }
+        }
+        let solution = solve(ata, atb);
+        for (a, &i) in free.iter().enumerate() {
+            gains[i] = solution[a];
+        }
+
+        let mut changed = false;
+        for &i in &free {
+            if gains[i].abs() > MAX_GAIN as f64 {
+                gains[i] = gains[i].clamp(-MAX_GAIN as f64, MAX_GAIN as f64);
+                pinned[i] = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut bands = [0.0f32; 11];
+    for (band, gain) in bands[1..].iter_mut().zip(gains) {
+        if !gain.is_finite() {
+            continue;
+        }
+        // the device takes 0.1 dB steps, + 0.0 turns -0.0 into 0.0
+        *band = ((gain * 10.0).round() / 10.0 + 0.0) as f32;
+    }
+
+    let fitted: Vec<f64> = freqs
+        .iter()
+        .map(|f| eq::bands_response(&bands[1..], *f))
+        .collect();
+    let peak = fitted.iter().cloned().fold(f64::MIN, f64::max);
+    bands[0] =
+        ((-peak).clamp(-MAX_GAIN as f64, 0.0) * 10.0).floor() as f32 / 10.0;
+
+    let (mut sum, mut max_error, mut max_error_freq) = (0.0, 0.0, MIN_FREQ);
+    for ((freq, fitted), target) in freqs.iter().zip(&fitted).zip(&targets) {
+        let error = fitted - target;
+        sum += error * error;
+        if error.abs() > max_error {
+            max_error = error.abs();
+            max_error_freq = *freq;
+        }
+    }
+
+    FitResult {
+        bands,
+        rms_error: (sum / freqs.len() as f64).sqrt(),
+        max_error,
+        max_error_freq,
+    }
+}
+
+/// Fits the bands to the combined response of a parametric EQ,
+/// the filters have to pass [`Filter::validate`]
+pub fn fit_filters(filters: &[Filter]) -> FitResult {
+    fit_curve(|freq| filters_response(filters, freq))
+}
+
+/// Gaussian elimination with partial pivoting.
+/// Singular systems (a band without any weight) solve to 0 for that band.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|x, y| a[*x][col].abs().total_cmp(&a[*y][col].abs()))
+            .unwrap_or(col);
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        if a[col][col].abs() < 1e-12 {
+            continue;
+        }
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        if a[row][row].abs() < 1e-12 {
+            continue;
+        }
+        let rest: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - rest) / a[row][row];
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peaking(freq: f64, gain: f64, q: f64) -> Filter {
+        Filter::Peaking { freq, gain, q }
+    }
+
+    #[test]
+    fn peaking_response_at_center_is_its_gain() {
+        let filter = peaking(1000.0, 6.0, 1.0);
+        assert!((filter.response(1000.0) - 6.0).abs() < 1e-6);
+        assert!(filter.response(20.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn shelves_reach_their_gain() {
+        let low = Filter::LowShelf {
+            freq: 105.0,
+            gain: 6.0,
+            q: 0.71,
+        };
+        let high = Filter::HighShelf {
+            freq: 8000.0,
+            gain: -4.0,
+            q: 0.71,
+        };
+        assert!((low.response(20.0) - 6.0).abs() < 0.2);
+        assert!(low.response(5000.0).abs() < 0.1);
+        assert!((high.response(20000.0) + 4.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn flat_fits_to_zero() {
+        let result = fit_filters(&[]);
+        assert_eq!(result.bands, [0.0; 11]);
+        assert_eq!(result.rms_error, 0.0);
+    }
+
+    #[test]
+    fn band_shaped_filter_fits_onto_its_band() {
+        let result = fit_filters(&[peaking(1000.0, 6.0, BAND_Q)]);
+        // bands[6] is 1 kHz
+        assert!((result.bands[6] - 6.0).abs() < 0.2, "{:?}", result.bands);
+        assert!(result.rms_error < 0.5);
+        assert!(result.bands[0] < 0.0, "the Pre-Amp makes room for the peak");
+    }
+
+    #[test]
+    fn bands_stay_within_range() {
+        let result = fit_filters(&[peaking(1000.0, 30.0, 0.5)]);
+        assert!(result.bands[1..].iter().all(|b| b.abs() <= MAX_GAIN));
+    }
+
+    #[test]
+    fn undefined_targets_give_finite_bands() {
+        let result =
+            fit_curve(|freq| if freq < 100.0 { f64::NAN } else { 3.0 });
+        assert!(result.bands.iter().all(|b| b.is_finite()));
+    }
+
+    #[test]
+    fn validate_rejects_undefined_filters() {
+        assert!(peaking(1000.0, 6.0, 1.0).validate().is_ok());
+        assert!(peaking(0.0, 6.0, 1.0).validate().is_err());
+        assert!(peaking(24000.0, 6.0, 1.0).validate().is_err());
+        assert!(peaking(f64::NAN, 6.0, 1.0).validate().is_err());
+        assert!(peaking(1000.0, 6.0, 0.0).validate().is_err());
+        assert!(peaking(1000.0, 6.0, -1.0).validate().is_err());
+        assert!(peaking(1000.0, f64::INFINITY, 1.0).validate().is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+use crate::Feature;
+
+/// Number of steps that can be undone
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Changes of the same thing within this window are merged into one step,
+/// so undoing a slider drag doesn't take one undo per tick
+pub const MERGE_WINDOW: Duration = Duration::from_millis(1500);
+
+/// One state transition, e.g. a slider change or a whole profile apply.
+/// Only the features that changed are kept, so undoing a step leaves
+/// changes made meanwhile to other features alone, e.g. by an app rule.
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub label: String,
+    /// The changed features with their old values
+    pub before: Vec<Feature>,
+    /// The same features with their new values
+    pub after: Vec<Feature>,
+    /// When the step was last extended, `None` once it must not merge anymore
+    at: Option<Instant>,
+}
+
+/// Bounded undo / redo history of the feature state
+#[derive(Default)]
+pub struct History {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HistorySummary {
+    /// Labels of the steps that can be undone, most recent last
+    pub undo: Vec<String>,
+    /// Labels of the steps that can be redone, next one last
+    pub redo: Vec<String>,
+}
+
+impl History {
+    /// Records a transition, unless nothing changed.
+    /// Clears everything that could have been redone.
+    pub fn record(
+        &mut self,
+        label: impl Into<String>,
+        before: Vec<Feature>,
+        after: Vec<Feature>,
+    ) {
+        let (before_changed, after_changed): (Vec<Feature>, Vec<Feature>) =
+            before
+                .iter()
+                .zip(&after)
+                .filter(|(b, a)| b.name == a.name && b.value != a.value)
+                .map(|(b, a)| (b.clone(), a.clone()))
+                .unzip();
+        if before_changed.is_empty() {
+            return;
+        }
+        let label = label.into();
+        self.redo.clear();
+
+        if let Some(last) = self.undo.back_mut()
+            && last.label == label
+            && last.at.is_some_and(|at| at.elapsed() < MERGE_WINDOW)
+            && last.after.iter().all(|f| before.contains(f))
+        {
+            for (b, a) in before_changed.into_iter().zip(after_changed) {
+                match last.after.iter().position(|f| f.name == a.name) {
+                    Some(i) => last.after[i] = a,
+                    None => {
+                        last.before.push(b);
+                        last.after.push(a);
+                    }
+                }
+            }
+            last.at = Some(Instant::now());
+            return;
+        }
+
+        self.undo.push_back(Step {
+            label,
+            before: before_changed,
+            after: after_changed,
+            at: Some(Instant::now()),
+        });
+        while self.undo.len() > HISTORY_LIMIT {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Takes the step to undo, hand it back with [`History::undone`]
+    pub fn take_undo(&mut self) -> Option<Step> {
+        self.undo.pop_back()
+    }
+
+    /// Takes the step to redo, hand it back with [`History::redone`]
+    pub fn take_redo(&mut self) -> Option<Step> {
+        self.redo.pop()
+    }
+
+    pub fn undone(&mut self, step: Step) {
+        self.redo.push(step);
+    }
+
+    pub fn redone(&mut self, mut step: Step) {
+        // a redone step must never be merged with what comes next
+        step.at = None;
+        self.undo.push_back(step);
+    }
+
+    /// Puts a step back where it was taken from, if applying it failed
+    pub fn restore_undo(&mut self, step: Step) {
+        self.undo.push_back(step);
+    }
+
+    pub fn restore_redo(&mut self, step: Step) {
+        self.redo.push(step);
+    }
+
+    pub fn summary(&self) -> HistorySummary {
+        HistorySummary {
+            undo: self.undo.iter().map(|s| s.label.clone()).collect(),
+            redo: self.redo.iter().map(|s| s.label.clone()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FEATURES, FeatureType};
+
+    fn with_slider(
+        features: &[Feature],
+        name: &str,
+        value: f32,
+    ) -> Vec<Feature> {
+        let mut features = features.to_vec();
+        let feature = features.iter_mut().find(|f| f.name == name).unwrap();
+        feature.value = FeatureType::Slider(value);
+        features
+    }
+
+    #[test]
+    fn steps_keep_only_changed_features() {
+        let mut history = History::default();
+        let start = FEATURES.to_vec();
+        let first = with_slider(&start, "EQ 31Hz", 3.0);
+        let second = with_slider(&first, "EQ 62Hz", -2.0);
+
+        history.record("EQ", start.clone(), first.clone());
+        history.record("EQ", first, second);
+
+        // merged into one step, with both bands but nothing else
+        let step = history.take_undo().unwrap();
+        assert!(history.take_undo().is_none());
+        let names: Vec<&str> = step.after.iter().map(|f| f.name).collect();
+        assert_eq!(names, ["EQ 31Hz", "EQ 62Hz"]);
+        assert!(step.before.iter().all(|f| start.contains(f)));
+    }
+
+    #[test]
+    fn unchanged_state_is_not_recorded() {
+        let mut history = History::default();
+        history.record("Nothing", FEATURES.to_vec(), FEATURES.to_vec());
+        assert!(history.take_undo().is_none());
+    }
+}
@@ -1,21 +1,56 @@
 #![allow(unused)]
 
-use hidapi::{DeviceInfo, HidApi, HidDevice};
+use hidapi::{DeviceInfo, HidApi, HidDevice, HidResult};
 use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::fs::{self, File, create_dir_all};
-use std::io::{BufReader, BufWriter, ErrorKind};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
 use std::ops::{Deref, DerefMut};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
 use tracing::{debug, warn};
+use utoipa::ToSchema;
+
+use crate::api::MixerSetRequest;
+use crate::events::{Event, Events};
+use crate::loudness::Loudness;
+use crate::preamp::AutoPreAmp;
+use crate::profile::{
+    ActiveLayer, Layer, OverrideId, Overrides, Profile, Snapshot,
+};
+use crate::tone::ToneControls;
 
 // #[cfg(test)]
 // mod tests;
+pub mod abx;
 pub mod api;
+pub mod apo;
+pub mod autoeq;
+pub mod autoeq_parse;
+pub mod autoswitch;
+pub mod config;
+pub mod eq;
+pub mod events;
+pub mod export;
+pub mod fit;
+pub mod history;
+pub mod loudness;
+pub mod openapi;
+pub mod persist;
+pub mod preamp;
+pub mod presets;
+pub mod profile;
+pub mod ramp;
+pub mod schedule;
 pub mod server;
+pub mod share;
+pub mod target;
+pub mod tone;
+pub mod watch;
 
 pub const VENDOR_ID: u16 = 0x041e;
 pub const PRODUCT_ID: u16 = 0x3256;
@@ -190,7 +225,7 @@ pub const FEATURES: &[Feature] = &[
     },
 ];
 
-#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub enum Format {
     Global(u8),
     SBX(u8),
@@ -204,7 +239,7 @@ impl Display for Format {
     }
 }
 
-#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub enum FeatureType {
     Toggle(bool),
     Slider(f32),
@@ -276,7 +311,7 @@ impl FeatureType {
     }
 }
 
-#[derive(PartialEq, Clone, Debug, Serialize)]
+#[derive(PartialEq, Clone, Debug, Serialize, ToSchema)]
 pub struct Feature {
     pub name: &'static str,
     pub id: Format,
@@ -311,15 +346,43 @@ impl<'de> Deserialize<'de> for Feature {
     }
 }
 
+/// Where payloads are written to, the G6 or a stand-in in tests
+pub trait Connection: Send {
+    fn write(&self, data: &[u8]) -> HidResult<usize>;
+}
+
+impl Connection for HidDevice {
+    fn write(&self, data: &[u8]) -> HidResult<usize> {
+        HidDevice::write(self, data)
+    }
+}
+
 #[derive(Serialize)]
 pub struct BlasterXG6 {
     pub features: Vec<Feature>,
+    /// `None` without a G6, in tests
     #[serde(skip)]
-    pub device: DeviceInfo,
+    pub device: Option<DeviceInfo>,
     #[serde(skip)]
-    pub connection: HidDevice,
+    pub connection: Box<dyn Connection>,
     #[serde(skip)]
     pub profile_path: PathBuf,
+    /// Where the layers currently on the device came from
+    #[serde(skip)]
+    pub active_layers: BTreeMap<Layer, ActiveLayer>,
+    /// Keeps the EQ from clipping, see [`crate::preamp`]
+    pub auto_preamp: AutoPreAmp,
+    /// Added to the bands on the device only, see [`crate::loudness`]
+    pub loudness: Loudness,
+    /// The tone controls the EQ came from,
+    /// `None` once it got changed otherwise
+    pub tone: Option<ToneControls>,
+    /// Every change is published here, see [`crate::events`]
+    #[serde(skip)]
+    pub events: Events,
+    /// Profiles applied by app rules and schedules
+    #[serde(skip)]
+    pub overrides: Overrides,
 }
 
 impl BlasterXG6 {
@@ -329,29 +392,45 @@ impl BlasterXG6 {
         let connection = device.open_device(&api)?;
         let _ = connection.set_blocking_mode(false);
 
-        let mut device_struct = Self {
-            features: FEATURES.to_vec(),
-            device,
-            connection,
-            profile_path: PathBuf::from(format!(
-                "{}linuxblaster/profiles/",
-                env::var("XDG_DATA_HOME").unwrap_or_else(|_| format!(
-                    "{}/.local/share/",
-                    env::var("HOME").expect("HOME is not set")
-                )),
-            )),
-        };
+        let mut device_struct = Self::with_connection(Box::new(connection));
+        device_struct.device = Some(device);
 
         let default_profile = device_struct.profile_path.join("default.json");
-        if default_profile.exists() {
-            if let Err(e) = device_struct.apply_profile(default_profile) {
-                warn!("Failed to apply default profile on startup: {}", e);
+        if default_profile.exists()
+            && let Err(e) = device_struct.apply_profile(default_profile.clone())
+        {
+            warn!("Failed to apply default profile on startup: {}", e);
+
+            // a broken default.json shouldn't cost the user their setup,
+            // the previous version is still around as a backup
+            let backup = backup_path(&default_profile);
+            if backup.exists() {
+                warn!("Falling back to {}", backup.display());
+                if let Err(e) = device_struct.apply_profile(backup) {
+                    warn!("Failed to apply backup profile: {}", e);
+                }
             }
         }
 
         Ok(device_struct)
     }
 
+    /// All features at their defaults, nothing is sent until they change
+    pub fn with_connection(connection: Box<dyn Connection>) -> Self {
+        Self {
+            features: FEATURES.to_vec(),
+            device: None,
+            connection,
+            profile_path: default_profile_dir(),
+            active_layers: BTreeMap::new(),
+            auto_preamp: AutoPreAmp::default(),
+            loudness: Loudness::default(),
+            tone: None,
+            events: Events::default(),
+            overrides: Overrides::default(),
+        }
+    }
+
     /// Loads a profile from a file and creates a new BlasterXG6
     pub fn from_profile(path: PathBuf) -> Result<Self, Box<dyn Error>> {
         todo!()
@@ -387,52 +466,310 @@ impl BlasterXG6 {
                 }
             });
 
-        let file = File::create(path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &changed_features)?;
+        let json = serde_json::to_vec_pretty(&changed_features)?;
+        write_atomic(&path, &json)?;
         debug!("Profile saved ¯\\_(ツ)_/¯");
         debug!("===== save_profile completed =====");
 
         Ok(())
     }
 
+    /// Applies every layer stored in a profile file
     pub fn apply_profile(
         &mut self,
         path: PathBuf,
     ) -> Result<(), Box<dyn Error>> {
-        let features: Vec<Feature> = self.open_profile(path)?;
-        features.iter().try_for_each(|feature| {
-            match feature.value {
-                FeatureType::Toggle(value) => {
-                    debug!("Applying toggle: {}", feature.name);
-                    debug!("- value: {}", value);
-                    self.set_feature(feature.name, Some(value))?;
+        self.apply_profile_layers(path, None)
+    }
+
+    /// Applies the given layers of a profile file, or all of them if `None`.
+    /// Layers the file doesn't contain are skipped.
+    pub fn apply_profile_layers(
+        &mut self,
+        path: PathBuf,
+        layers: Option<&[Layer]>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut profile = self.open_profile(path.clone())?;
+        if let Some(layers) = layers {
+            profile.retain_layers(layers);
+        }
+
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.apply_layered_profile(&name, &profile)
+    }
+
+    /// Applies all layers present in a profile, in the order of [`Layer::ALL`]
+    /// and records `name` as their source in `active_layers`
+    pub fn apply_layered_profile(
+        &mut self,
+        name: &str,
+        profile: &Profile,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut profile = profile.clone();
+        tone::resolve(&mut profile, &self.profile_path)?;
+
+        for layer in profile.layers() {
+            debug!("Applying layer {} of profile {}", layer, name);
+
+            if layer == Layer::Mixer {
+                for (control, levels) in profile.mixer.iter().flatten() {
+                    api::write_mixer(&MixerSetRequest::from_levels(
+                        control, levels,
+                    ));
+                    self.events.publish(Event::Mixer {
+                        name: control.clone(),
+                        levels: levels.clone(),
+                    });
                 }
-                FeatureType::Slider(value) => {
-                    if value.abs() > 0.0 {
-                        debug!("Applying slider: {}", feature.name);
-                        debug!("- value: {}", value);
-                        self.set_slider(feature.name, value)?;
-                    }
+            } else if let Some(targets) = profile.layer_targets(layer) {
+                self.transition(&targets)?;
+            }
+            if layer == Layer::Eq {
+                self.tone = profile.tone.clone();
+            }
+
+            self.active_layers.insert(
+                layer,
+                ActiveLayer {
+                    profile: name.to_string(),
+                    modified: false,
+                },
+            );
+        }
+        self.publish_profile();
+        if profile.layers().contains(&Layer::Eq) {
+            self.publish_eq();
+        }
+        Ok(())
+    }
+
+    /// Applies (some layers of) a named profile until
+    /// [`BlasterXG6::revert_temporary`] is called with the returned id
+    pub fn apply_temporarily(
+        &mut self,
+        name: &str,
+        layers: Option<&[Layer]>,
+    ) -> Result<OverrideId, Box<dyn Error>> {
+        let mut profile = self.open_named_profile(name)?;
+        if let Some(layers) = layers {
+            profile.retain_layers(layers);
+        }
+
+        let id = self.overrides.next_id();
+        if let Err(e) = self.engage(id, name, profile) {
+            // don't leave a half applied profile behind
+            let _ = self.revert_temporary(id);
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    fn engage(
+        &mut self,
+        id: OverrideId,
+        name: &str,
+        profile: Profile,
+    ) -> Result<(), Box<dyn Error>> {
+        let current = self.to_profile(&profile.layers());
+        self.overrides.extend_base(current, &self.active_layers);
+        let applied = self.apply_layered_profile(name, &profile);
+        self.overrides.applied.push((id, name.to_string(), profile));
+        applied
+    }
+
+    /// Ends a temporary profile. The state from before any of them took
+    /// over comes back, then the ones still in effect are applied again.
+    pub fn revert_temporary(
+        &mut self,
+        id: OverrideId,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(position) =
+            self.overrides.applied.iter().position(|(i, _, _)| *i == id)
+        else {
+            return Ok(());
+        };
+        self.overrides.applied.remove(position);
+        let Some(base) = self.overrides.base.take() else {
+            return Ok(());
+        };
+        if let Err(e) = self.restore(&base) {
+            // still the way back for the next revert
+            self.overrides.base = Some(base);
+            return Err(e);
+        }
+
+        let mut result = Ok(());
+        let applied = std::mem::take(&mut self.overrides.applied);
+        for (id, name, profile) in applied {
+            if let Err(e) = self.engage(id, &name, profile) {
+                warn!("Failed to apply profile {} again: {}", name, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Goes back to the state of a [`Snapshot`]
+    pub fn restore(
+        &mut self,
+        snapshot: &Snapshot,
+    ) -> Result<(), Box<dyn Error>> {
+        self.apply_layered_profile("", &snapshot.profile)?;
+
+        // the layers came from wherever they came from before
+        for layer in snapshot.profile.layers() {
+            match snapshot.active_layers.get(&layer) {
+                Some(active) => {
+                    self.active_layers.insert(layer, active.clone());
+                }
+                None => {
+                    self.active_layers.remove(&layer);
                 }
             }
-            Ok::<(), Box<dyn Error>>(())
-        })?;
+        }
+        self.publish_profile();
         Ok(())
     }
 
+    /// Reads a profile, with its tone controls resolved
     pub fn open_profile(
         &self,
         path: PathBuf,
-    ) -> Result<Vec<Feature>, Box<dyn Error>> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let features: Vec<Feature> = serde_json::from_reader(reader)?;
-        Ok(features.clone())
+    ) -> Result<Profile, Box<dyn Error>> {
+        let mut profile = Profile::load(&path)?;
+        tone::resolve(&mut profile, &self.profile_path)?;
+        Ok(profile)
+    }
+
+    /// Reads a profile or a built-in preset by name,
+    /// with its tone controls resolved
+    pub fn open_named_profile(
+        &self,
+        name: &str,
+    ) -> Result<Profile, Box<dyn Error>> {
+        let mut profile = presets::load_named(&self.profile_path, name)?;
+        tone::resolve(&mut profile, &self.profile_path)?;
+        Ok(profile)
+    }
+
+    /// Captures the current state of the given layers as a profile
+    pub fn to_profile(&self, layers: &[Layer]) -> Profile {
+        let mut profile = Profile::from_features(
+            &self
+                .features
+                .iter()
+                .filter(|f| Some(&f.value) != default_value(f.name))
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        if layers.contains(&Layer::Mixer) {
+            profile.mixer = Some(api::read_mixer().into_iter().collect());
+        }
+        // the EQ is stored as the tone controls it came from
+        if let Some(tone) = &self.tone {
+            profile.eq = None;
+            profile.tone = Some(tone.clone());
+        }
+        profile.retain_layers(layers);
+        profile
+    }
+
+    /// Saves the given layers of the current state as a layered profile
+    pub fn save_layered_profile(
+        &self,
+        path: PathBuf,
+        layers: &[Layer],
+    ) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_vec_pretty(&self.to_profile(layers))?;
+        write_atomic(&path, &json)
+    }
+
+    /// Path of a named profile in the profile directory
+    /// ### Errors
+    /// - if the name is empty or could escape the profile directory
+    pub fn named_profile_path(
+        &self,
+        name: &str,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        named_profile_path_in(&self.profile_path, name)
+    }
+
+    /// Names of all profiles in the profile directory, sorted
+    pub fn list_profiles(&self) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(&self.profile_path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|path| {
+                path.file_stem().map(|s| s.to_string_lossy().to_string())
+            })
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Moves the device to the given feature values.
+    /// Only features whose value differs are written.
+    /// Features that aren't listed are left alone.
+    pub fn transition(
+        &mut self,
+        targets: &[Feature],
+    ) -> Result<(), Box<dyn Error>> {
+        let edits_eq = targets.iter().any(|target| {
+            Layer::of(target) == Layer::Eq
+                && self
+                    .get_feature(target.name)
+                    .is_ok_and(|(current, _)| current.value != target.value)
+        });
+        if edits_eq {
+            // profiles put their own tone controls back afterwards
+            self.drop_tone();
+        }
+
+        // sliders first, like in reset(): setting one switches its
+        // dependencies on, the toggles below may turn them off again
+        for target in targets {
+            let FeatureType::Slider(value) = target.value else {
+                continue;
+            };
+            let (current, _) = self.get_feature(target.name)?;
+            if current.value == target.value {
+                continue;
+            }
+            debug!("Transition slider {} -> {}", target.name, value);
+            if value == 0.0 {
+                // zeroing a slider shouldn't switch its dependencies on
+                self.write_value(target.name, target.value.clone())?;
+            } else {
+                self.write_slider(target.name, value)?;
+            }
+        }
+
+        for target in targets {
+            let FeatureType::Toggle(value) = target.value else {
+                continue;
+            };
+            let (current, _) = self.get_feature(target.name)?;
+            if current.value == target.value {
+                continue;
+            }
+            debug!("Transition toggle {} -> {}", target.name, value);
+            self.set_feature(target.name, Some(value))?;
+        }
+
+        // the Pre-Amp of the targets doesn't count as a manual edit
+        self.update_auto_preamp()
     }
 
     /// Resets all features to their default state (Sliders: 0, Toggles: Off)
     pub fn reset(&mut self) -> Result<(), Box<dyn Error>> {
+        self.drop_tone();
         // reset sliders first, in case they can't be changed after toggles are off
         // don't know if necessary, hard to know with a reverse engineering protocol
 
@@ -447,8 +784,11 @@ impl BlasterXG6 {
         for name in slider_names {
             // EQ sliders are 0x0A-0x14, which use raw values. 0.0 is 0dB (flat).
             // Other sliders use 0-100 range, so 0.0 is 0%.
-            self.set_slider(&name, 0.0)?;
+            self.write_slider(&name, 0.0)?;
         }
+        self.auto_preamp.offset = 0.0;
+        self.update_auto_preamp()?;
+        self.publish_eq();
 
         // Toggles
         let toggle_names: Vec<String> = self
@@ -584,12 +924,7 @@ impl BlasterXG6 {
         bands[0] = self.get_feature("EQ Pre-Amp").ok()?.0.value.as_f32()?;
 
         for (idx, band) in ISO_BANDS.iter().enumerate() {
-            let feature_name = if *band < 1000.0 {
-                format!("EQ {}Hz", band)
-            } else {
-                format!("EQ {}kHz", band / 1000.0)
-            };
-            let Ok(feature) = self.get_feature(feature_name) else {
+            let Ok(feature) = self.get_feature(eq_band_name(*band)) else {
                 return None;
             };
             bands[idx + 1] = feature.0.value.as_f32().unwrap_or(0.0);
@@ -598,6 +933,62 @@ impl BlasterXG6 {
         Some(bands)
     }
 
+    /// What the EQ does right now, i.e. [`BlasterXG6::get_ten_band_eq`],
+    /// or flat while the Equalizer (or SBX) is off
+    pub fn active_ten_band_eq(&self) -> [f32; 11] {
+        let is_on = |name: &str| {
+            self.get_feature(name)
+                .is_ok_and(|(f, _)| f.value.as_bool() == Some(true))
+        };
+        if !is_on("SBX") || !is_on("Equalizer") {
+            return [0.0; 11];
+        }
+        self.get_ten_band_eq().unwrap_or([0.0; 11])
+    }
+
+    /// Sets Pre-Amp and EQ Bands in the layout of
+    /// [`BlasterXG6::get_ten_band_eq`] and turns the Equalizer on.
+    /// Values are clamped to ±12 dB.
+    pub fn set_ten_band_eq(
+        &mut self,
+        bands: [f32; 11],
+    ) -> Result<(), Box<dyn Error>> {
+        // clamp() keeps NaN as it is
+        if bands.iter().any(|band| !band.is_finite()) {
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "EQ bands must be numbers",
+            )));
+        }
+        let mut targets: Vec<Feature> = Vec::new();
+        for (name, value) in ten_band_eq_names().iter().zip(bands) {
+            let mut feature = self.get_feature(name.as_str())?.0.clone();
+            feature.value = FeatureType::Slider(value.clamp(-12.0, 12.0));
+            targets.push(feature);
+        }
+        let mut equalizer = self.get_feature("Equalizer")?.0.clone();
+        equalizer.value = FeatureType::Toggle(true);
+        targets.push(equalizer);
+
+        self.transition(&targets)
+    }
+
+    /// Writes Pre-Amp and EQ Bands in the layout of
+    /// [`BlasterXG6::get_ten_band_eq`] as they are, for the steps of
+    /// [`crate::ramp`]. Dependencies and the auto Pre-Amp are left alone.
+    pub fn write_ten_band_eq(
+        &mut self,
+        bands: &[f32; 11],
+    ) -> Result<(), Box<dyn Error>> {
+        for (name, value) in ten_band_eq_names().iter().zip(bands) {
+            let value = FeatureType::Slider(*value);
+            if self.get_feature(name.as_str())?.0.value != value {
+                self.write_value(name, value)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Sets the Value of a Feature to On of Off
     /// ### **None**:
     /// - Toggles the feature between On and Off
@@ -653,6 +1044,12 @@ impl BlasterXG6 {
         debug!("- final_value: {}", final_value);
 
         let feature_name: String = feature.clone().into();
+        // tone controls mean the Equalizer is on
+        if feature_name == "Equalizer"
+            && f_value != FeatureType::Toggle(final_value)
+        {
+            self.drop_tone();
+        }
         // Mutually exclusive: SBX and Scout Mode cannot be on at the same time
         if final_value {
             if feature_name == "SBX" {
@@ -743,11 +1140,69 @@ impl BlasterXG6 {
     }
 
     /// Sets the Value of a Slider Feature
-    /// Also sets any required dependencies to On
+    /// Also sets any required dependencies to On.
+    /// With [`AutoPreAmp`] on, band changes move the Pre-Amp along
+    /// and Pre-Amp changes are handled as in [`AutoPreAmp::manual_edit`].
     pub fn set_slider(
         &mut self,
         feature: &str,
         value: f32,
+    ) -> Result<(), Box<dyn Error>> {
+        if feature.starts_with("EQ ") {
+            self.drop_tone();
+        }
+        if feature == "EQ Pre-Amp" && self.auto_preamp.is_active() {
+            let gains = self.eq_gains();
+            self.auto_preamp.manual_edit(&gains, value);
+            self.publish_eq();
+        }
+        self.write_slider(feature, value)?;
+
+        if feature != "EQ Pre-Amp" && feature.starts_with("EQ ") {
+            self.update_auto_preamp()?;
+        }
+        Ok(())
+    }
+
+    /// Moves the Pre-Amp to [`AutoPreAmp::preamp`] if auto mode is on
+    pub fn update_auto_preamp(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.auto_preamp.is_active() {
+            return Ok(());
+        }
+        let preamp = self.auto_preamp.preamp(&self.eq_gains());
+        let current = self.get_feature("EQ Pre-Amp")?.0.value.clone();
+        if current != FeatureType::Slider(preamp) {
+            debug!("Auto Pre-Amp -> {}", preamp);
+            self.write_value("EQ Pre-Amp", FeatureType::Slider(preamp))?;
+        }
+        Ok(())
+    }
+
+    /// The 10 bands without the Pre-Amp, as they are sent to the device
+    fn eq_gains(&self) -> [f32; 10] {
+        match self.get_ten_band_eq() {
+            Some(bands) => self.compensated_gains(&bands[1..]),
+            None => [0.0; 10],
+        }
+    }
+
+    /// Band gains with the loudness compensation added,
+    /// what the auto Pre-Amp has to make room for
+    pub fn compensated_gains(&self, gains: &[f32]) -> [f32; 10] {
+        let mut compensated = [0.0; 10];
+        for ((compensated, gain), band) in
+            compensated.iter_mut().zip(gains).zip(ISO_BANDS)
+        {
+            *compensated = self.compensated(&eq_band_name(band), *gain);
+        }
+        compensated
+    }
+
+    /// [`BlasterXG6::set_slider`] without the auto Pre-Amp
+    fn write_slider(
+        &mut self,
+        feature: &str,
+        value: f32,
     ) -> Result<(), Box<dyn Error>> {
         let (f_id, f_value, dependencies) = {
             let (f, dependencies) = self.get_feature(feature)?;
@@ -776,15 +1231,103 @@ impl BlasterXG6 {
             })?;
         }
 
-        let payload = create_payload(f_id, value);
+        self.write_value(feature, FeatureType::Slider(value))
+    }
+
+    /// Sends a value to the device and stores it,
+    /// without touching dependencies or dependents
+    fn write_value(
+        &mut self,
+        feature: &str,
+        value: FeatureType,
+    ) -> Result<(), Box<dyn Error>> {
+        let f_id = self.get_feature(feature)?.0.id.clone();
+        let raw = match value {
+            FeatureType::Toggle(on) => {
+                if on {
+                    100.0
+                } else {
+                    0.0
+                }
+            }
+            FeatureType::Slider(value) => self.compensated(feature, value),
+        };
+
+        let payload = create_payload(f_id, raw);
         self.connection.write(&payload.data)?;
         self.connection.write(&payload.commit)?;
 
-        self.update_feature_value(feature, FeatureType::Slider(value))?;
+        self.update_feature_value(feature, value)
+    }
 
+    /// A slider value as it's sent to the device,
+    /// with the loudness compensation of EQ Bands added
+    fn compensated(&self, feature: &str, value: f32) -> f32 {
+        let offset = self.loudness.offset(feature);
+        if offset == 0.0 {
+            return value;
+        }
+        (value + offset).clamp(-12.0, 12.0)
+    }
+
+    /// Follows a new playback volume, or changed settings if `None`,
+    /// and resends the bands whose loudness compensation changed.
+    /// The stored band values stay as they are.
+    pub fn update_loudness(
+        &mut self,
+        volume: Option<f32>,
+    ) -> Result<(), Box<dyn Error>> {
+        let before = self.loudness.offsets;
+        if volume.is_some() {
+            self.loudness.volume = volume;
+        }
+        self.loudness.recompute();
+
+        for ((band, before), after) in
+            ISO_BANDS.iter().zip(before).zip(self.loudness.offsets)
+        {
+            if before == after {
+                continue;
+            }
+            let name = eq_band_name(*band);
+            let (feature, _) = self.get_feature(name.as_str())?;
+            let id = feature.id.clone();
+            let value = feature.value.as_f32().unwrap_or(0.0);
+
+            debug!("Loudness {} {:+.1} dB", name, after);
+            let payload = create_payload(id, self.compensated(&name, value));
+            self.connection.write(&payload.data)?;
+            self.connection.write(&payload.commit)?;
+        }
+        // the boost needs headroom as much as a band the user raised
+        self.update_auto_preamp()?;
+        self.publish_eq();
         Ok(())
     }
 
+    /// The EQ got edited by hand, it doesn't come from the tone controls
+    /// anymore. Not for the Pre-Amp moved by auto Pre-Amp or loudness.
+    fn drop_tone(&mut self) {
+        if self.tone.take().is_some() {
+            self.publish_eq();
+        }
+    }
+
+    /// Publishes auto Pre-Amp, loudness and tone controls
+    pub fn publish_eq(&self) {
+        self.events.publish(Event::Eq {
+            auto_preamp: self.auto_preamp.clone(),
+            loudness: self.loudness.clone(),
+            tone: self.tone.clone(),
+        });
+    }
+
+    fn publish_profile(&self) {
+        self.events.publish(Event::Profile {
+            active_layers: self.active_layers.clone(),
+        });
+    }
+
     fn update_feature_value(
         &mut self,
         feature: impl Into<String> + Clone,
@@ -804,7 +1347,22 @@ impl BlasterXG6 {
                 feature.clone().into(),
                 value
             );
-            feature_entry.value = value;
+            // ramps write the same values over and over
+            if feature_entry.value != value {
+                feature_entry.value = value.clone();
+                self.events.publish(Event::Feature {
+                    name: feature_entry.name.to_string(),
+                    value,
+                });
+            }
+
+            let layer = Layer::of(feature_entry);
+            if let Some(active) = self.active_layers.get_mut(&layer)
+                && !active.modified
+            {
+                active.modified = true;
+                self.publish_profile();
+            }
             return Ok(());
         }
 
@@ -820,6 +1378,142 @@ impl BlasterXG6 {
     }
 }
 
+/// `$XDG_DATA_HOME/linuxblaster/profiles/`
+pub fn default_profile_dir() -> PathBuf {
+    PathBuf::from(format!(
+        "{}linuxblaster/profiles/",
+        env::var("XDG_DATA_HOME").unwrap_or_else(|_| format!(
+            "{}/.local/share/",
+            env::var("HOME").expect("HOME is not set")
+        )),
+    ))
+}
+
+/// See [`BlasterXG6::named_profile_path`]
+pub fn named_profile_path_in(
+    dir: &Path,
+    name: &str,
+) -> Result<PathBuf, Box<dyn Error>> {
+    if presets::is_builtin(name) {
+        return Err(Box::new(std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("Preset {} is read-only", name),
+        )));
+    }
+    if name.trim().is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\', '\0'])
+    {
+        return Err(Box::new(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid profile name: {:?}", name),
+        )));
+    }
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+/// Feature name of an EQ band, e.g. `EQ 125Hz`, `EQ 2kHz`
+fn eq_band_name(band: f64) -> String {
+    if band < 1000.0 {
+        format!("EQ {}Hz", band)
+    } else {
+        format!("EQ {}kHz", band / 1000.0)
+    }
+}
+
+/// Names of Pre-Amp and EQ Bands,
+/// in the layout of [`BlasterXG6::get_ten_band_eq`]
+fn ten_band_eq_names() -> Vec<String> {
+    let mut names = vec!["EQ Pre-Amp".to_string()];
+    names.extend(ISO_BANDS.iter().map(|band| eq_band_name(*band)));
+    names
+}
+
+/// The value a feature has in [`FEATURES`], i.e. after a reset
+pub fn default_value(feature: &str) -> Option<&'static FeatureType> {
+    FEATURES
+        .iter()
+        .find(|f| f.name == feature)
+        .map(|f| &f.value)
+}
+
+/// Path of the backup that [`write_atomic`] keeps of the previous version
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Hashes of what [`write_atomic`] wrote last per file,
+/// so the profile watcher can tell our own writes from external edits
+static OWN_WRITES: LazyLock<Mutex<HashMap<PathBuf, u64>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Whether `bytes` is exactly what this process last wrote to `path`
+pub fn is_own_write(path: &Path, bytes: &[u8]) -> bool {
+    OWN_WRITES.lock().unwrap().get(path) == Some(&content_hash(bytes))
+}
+
+/// Replaces the contents of a file without ever leaving it half written.
+/// ### Steps
+/// - The data is written to a hidden temp file next to the target and synced
+/// - The current version of the target is copied to [`backup_path`]
+/// - The temp file is renamed over the target
+///
+/// A crash or a full disk at any point leaves either the old or the new
+/// version in place, never a truncated one.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    create_dir_all(parent)?;
+
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(path.file_name().unwrap_or_default());
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let write_tmp = || -> std::io::Result<()> {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()
+    };
+    if let Err(e) = write_tmp() {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(Box::new(e));
+    }
+
+    if path.exists()
+        && let Err(e) = fs::copy(path, backup_path(path))
+    {
+        warn!("Failed to back up {}: {}", path.display(), e);
+    }
+
+    OWN_WRITES
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), content_hash(bytes));
+
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(Box::new(e));
+    }
+
+    // make the rename itself durable; not every filesystem supports this
+    if let Ok(dir) = File::open(parent) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
 pub struct Payload {
     data: [u8; 65],
     commit: [u8; 65],
@@ -943,3 +1637,42 @@ fn format_hex(bytes: &[u8]) -> String {
             .join(", ")
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Takes every payload, no G6 needed
+    struct Discard;
+
+    impl Connection for Discard {
+        fn write(&self, data: &[u8]) -> HidResult<usize> {
+            Ok(data.len())
+        }
+    }
+
+    #[test]
+    fn tone_survives_auto_preamp_and_loudness() {
+        let mut device = BlasterXG6::with_connection(Box::new(Discard));
+        device.auto_preamp.enabled = true;
+        let tone = ToneControls {
+            bass: 6.0,
+            ..Default::default()
+        };
+        let bands = tone.ten_band_eq(&[0.0; 11]);
+        device.set_ten_band_eq(bands).unwrap();
+        device.tone = Some(tone.clone());
+        device.update_auto_preamp().unwrap();
+        let preamp = device.get_ten_band_eq().unwrap()[0];
+
+        // the boosted bass needs more headroom, the Pre-Amp moves
+        device.loudness.settings.enabled = true;
+        device.update_loudness(Some(0.2)).unwrap();
+        assert!(device.get_ten_band_eq().unwrap()[0] < preamp);
+        assert_eq!(device.tone, Some(tone));
+        assert!(device.to_profile(&[Layer::Eq]).tone.is_some());
+
+        device.set_slider("EQ 1kHz", 2.0).unwrap();
+        assert_eq!(device.tone, None);
+    }
+}
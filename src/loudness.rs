@@ -0,0 +1,295 @@
+//! Volume dependent loudness compensation.
+//! At low volumes bass and treble are boosted along the ISO 226:2003
+//! equal-loudness contours, on top of the bands of the user.
+//! The bands in the features and profiles stay what the user set,
+//! the compensation is only added when they are sent to the device.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::ISO_BANDS;
+use crate::api::AppState;
+use crate::eq::{self, MAX_GAIN};
+use crate::write_atomic;
+
+/// The contours are defined from 20 phon up
+pub const MIN_PHON: f64 = 20.0;
+pub const MAX_PHON: f64 = 90.0;
+
+/// ISO 226:2003 table: frequency, exponent `af`,
+/// transfer function magnitude `Lu` and hearing threshold `Tf`
+const ISO_226: [(f64, f64, f64, f64); 29] = [
+    (20.0, 0.532, -31.6, 78.5),
+    (25.0, 0.506, -27.2, 68.7),
+    (31.5, 0.480, -23.0, 59.5),
+    (40.0, 0.455, -19.1, 51.1),
+    (50.0, 0.432, -15.9, 44.0),
+    (63.0, 0.409, -13.0, 37.5),
+    (80.0, 0.387, -10.3, 31.5),
+    (100.0, 0.367, -8.1, 26.5),
+    (125.0, 0.349, -6.2, 22.1),
+    (160.0, 0.330, -4.5, 17.9),
+    (200.0, 0.315, -3.1, 14.4),
+    (250.0, 0.301, -2.0, 11.4),
+    (315.0, 0.288, -1.1, 8.6),
+    (400.0, 0.276, -0.4, 6.2),
+    (500.0, 0.267, 0.0, 4.4),
+    (630.0, 0.259, 0.3, 3.0),
+    (800.0, 0.253, 0.5, 2.2),
+    (1000.0, 0.250, 0.0, 2.4),
+    (1250.0, 0.246, -2.7, 3.5),
+    (1600.0, 0.244, -4.1, 1.7),
+    (2000.0, 0.243, -1.0, -1.3),
+    (2500.0, 0.243, 1.7, -4.2),
+    (3150.0, 0.243, 2.5, -6.0),
+    (4000.0, 0.242, 1.2, -5.4),
+    (5000.0, 0.242, -2.1, -1.5),
+    (6300.0, 0.245, -7.1, 6.0),
+    (8000.0, 0.254, -11.2, 12.6),
+    (10000.0, 0.271, -10.7, 13.9),
+    (12500.0, 0.301, -3.1, 12.3),
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoudnessSettings {
+    pub enabled: bool,
+    /// Playback volume (0.0 - 1.0) the music is mastered to be heard at,
+    /// no compensation there and above
+    pub reference_volume: f32,
+    /// Loudness in phon at the reference volume
+    pub reference_phon: f32,
+}
+
+impl Default for LoudnessSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            reference_volume: 1.0,
+            reference_phon: 80.0,
+        }
+    }
+}
+
+/// The settings that can be changed, all optional
+#[derive(Deserialize, Clone, Debug, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LoudnessUpdate {
+    pub enabled: Option<bool>,
+    pub reference_volume: Option<f32>,
+    pub reference_phon: Option<f32>,
+}
+
+/// Settings and the compensation that's currently applied
+#[derive(Serialize, Clone, Debug, Default, PartialEq, ToSchema)]
+pub struct Loudness {
+    #[serde(flatten)]
+    pub settings: LoudnessSettings,
+    /// Last playback volume that was read, 0.0 - 1.0
+    pub volume: Option<f32>,
+    /// dB added to each of the 10 bands
+    pub offsets: [f32; 10],
+}
+
+impl LoudnessSettings {
+    /// Reads the settings, a missing file means the defaults
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        let settings: Self = serde_json::from_str(&content)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_vec_pretty(self)?;
+        write_atomic(path, &json)
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !(self.reference_volume > 0.0 && self.reference_volume <= 1.0) {
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "Reference volume must be within 0 and 1",
+            )));
+        }
+        if !(MIN_PHON..=MAX_PHON).contains(&(self.reference_phon as f64)) {
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Reference loudness must be within {} and {} phon",
+                    MIN_PHON, MAX_PHON
+                ),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Applies the given settings, nothing changes if they are invalid
+    pub fn update(
+        &mut self,
+        update: &LoudnessUpdate,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut settings = self.clone();
+        if let Some(enabled) = update.enabled {
+            settings.enabled = enabled;
+        }
+        if let Some(reference_volume) = update.reference_volume {
+            settings.reference_volume = reference_volume;
+        }
+        if let Some(reference_phon) = update.reference_phon {
+            settings.reference_phon = reference_phon;
+        }
+        settings.validate()?;
+        *self = settings;
+        Ok(())
+    }
+
+    /// Loudness in phon at a playback volume.
+    /// The volume is a cubic PulseAudio / PipeWire volume, i.e. 60 dB per
+    /// decade.
+    pub fn phon_at(&self, volume: f32) -> f64 {
+        let ratio = volume as f64 / self.reference_volume as f64;
+        let attenuation = 60.0 * ratio.log10();
+        (self.reference_phon as f64 + attenuation)
+            .clamp(MIN_PHON, self.reference_phon as f64)
+    }
+}
+
+impl Loudness {
+    /// Recomputes the offsets for the stored volume,
+    /// all 0 while disabled or without a volume
+    pub fn recompute(&mut self) {
+        self.offsets = match self.volume {
+            Some(volume) if self.settings.enabled => band_compensation(
+                self.settings.phon_at(volume),
+                self.settings.reference_phon as f64,
+            ),
+            _ => [0.0; 10],
+        };
+    }
+
+    /// dB added to a feature when it's sent to the device
+    pub fn offset(&self, feature: &str) -> f32 {
+        ISO_BANDS
+            .iter()
+            .position(|band| crate::eq_band_name(*band) == feature)
+            .map_or(0.0, |idx| self.offsets[idx])
+    }
+}
+
+/// Sound pressure level in dB of a tone at table index `idx`
+/// that is as loud as `phon`
+fn equal_loudness_spl(idx: usize, phon: f64) -> f64 {
+    let (_, af, lu, tf) = ISO_226[idx];
+    let a = 4.47e-3 * (10f64.powf(0.025 * phon) - 1.15)
+        + (0.4 * 10f64.powf((tf + lu) / 10.0 - 9.0)).powf(af);
+    10.0 / af * a.log10() - lu + 94.0
+}
+
+/// How much more each frequency needs at `phon` than at `reference_phon`
+/// to keep the balance of the reference, 0 dB at 1 kHz
+pub fn compensation(phon: f64, reference_phon: f64) -> Vec<(f64, f64)> {
+    ISO_226
+        .iter()
+        .enumerate()
+        .map(|(idx, (freq, ..))| {
+            let at_phon = equal_loudness_spl(idx, phon) - phon;
+            let at_reference =
+                equal_loudness_spl(idx, reference_phon) - reference_phon;
+            (*freq, at_phon - at_reference)
+        })
+        .collect()
+}
+
+/// [`compensation`] at the 10 bands, in 0.1 dB steps within ±12 dB.
+/// Beyond 12.5 kHz the last value of the table is held.
+pub fn band_compensation(phon: f64, reference_phon: f64) -> [f32; 10] {
+    let curve = compensation(phon, reference_phon);
+    let mut offsets = [0.0; 10];
+    for (offset, band) in offsets.iter_mut().zip(ISO_BANDS) {
+        let gain = eq::interpolate(&curve, band) as f32;
+        *offset =
+            ((gain * 10.0).round() / 10.0 + 0.0).clamp(-MAX_GAIN, MAX_GAIN);
+    }
+    offsets
+}
+
+/// The settings live next to the profile directory, not inside it,
+/// so they don't show up as a profile
+pub fn loudness_path(profile_path: &Path) -> PathBuf {
+    profile_path
+        .parent()
+        .unwrap_or(profile_path)
+        .join("loudness.json")
+}
+
+/// Follows a playback volume read by [`crate::events::spawn_watcher`],
+/// which polls the mixer for both
+pub async fn follow_volume(state: &AppState, volume: f32) {
+    let mut device = state.device.lock().await;
+    if !device.loudness.settings.enabled
+        || device.loudness.volume == Some(volume)
+    {
+        return;
+    }
+    if let Err(e) = device
+        .update_loudness(Some(volume))
+        .map_err(|e| e.to_string())
+    {
+        warn!("Failed to apply loudness compensation: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_compensation_at_reference_volume() {
+        let settings = LoudnessSettings::default();
+        let phon = settings.phon_at(settings.reference_volume);
+        assert_eq!(phon, settings.reference_phon as f64);
+        assert_eq!(band_compensation(phon, phon), [0.0; 10]);
+    }
+
+    #[test]
+    fn low_volume_boosts_bass() {
+        let settings = LoudnessSettings::default();
+        let offsets = band_compensation(
+            settings.phon_at(0.3),
+            settings.reference_phon as f64,
+        );
+        assert!(offsets[0] > 0.0, "{offsets:?}");
+        // 1 kHz stays the reference
+        assert!(offsets[5].abs() < 0.5, "{offsets:?}");
+    }
+
+    #[test]
+    fn phon_is_clamped() {
+        let settings = LoudnessSettings::default();
+        assert_eq!(settings.phon_at(0.001), MIN_PHON);
+        assert_eq!(settings.phon_at(0.0), MIN_PHON);
+        assert_eq!(settings.phon_at(1.0), settings.reference_phon as f64);
+    }
+
+    #[test]
+    fn disabled_means_no_offsets() {
+        let mut loudness = Loudness {
+            volume: Some(0.1),
+            ..Default::default()
+        };
+        loudness.recompute();
+        assert_eq!(loudness.offsets, [0.0; 10]);
+
+        loudness.settings.enabled = true;
+        loudness.recompute();
+        assert_ne!(loudness.offsets, [0.0; 10]);
+    }
+}
@@ -1,4 +1,9 @@
-use linuxblaster_control::{BlasterXG6, server};
+use linuxblaster_control::{
+    BlasterXG6, apo, config, default_profile_dir, named_profile_path_in, server,
+    target, write_atomic,
+};
+use linuxblaster_control::fit::FitResult;
+use linuxblaster_control::profile::Profile;
 use tao::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoopBuilder},
@@ -13,15 +18,30 @@ use tray_icon::{
     MouseButton,
 };
 use wry::{WebViewBuilder, WebViewBuilderExtUnix};
-use tracing::Level;
 
 fn main() {
-    let start_minimized = std::env::args().any(|a| a == "--minimized");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let start_minimized = args.iter().any(|a| a == "--minimized");
+
+    // soundblaster-g6x --import-apo config.txt [--save-as name]
+    if let Some(path) = arg_value(&args, "--import-apo") {
+        import_apo(&path, arg_value(&args, "--save-as"));
+    }
+    // soundblaster-g6x --target-eq measurement.csv [--target harman_in_ear_2019|target.csv] [--save-as name]
+    if let Some(path) = arg_value(&args, "--target-eq") {
+        target_eq(&path, arg_value(&args, "--target"), arg_value(&args, "--save-as"));
+    }
+
+    let config = config::init(&args).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        std::process::exit(2);
+    });
+    let local_addr = format!("127.0.0.1:{}", config.port);
 
     // Try to connect to existing instance
-    if std::net::TcpStream::connect("127.0.0.1:3311").is_ok() {
+    if std::net::TcpStream::connect(&local_addr).is_ok() {
         // App is already running, ping it to show window
-        if let Ok(mut stream) = std::net::TcpStream::connect("127.0.0.1:3311") {
+        if let Ok(mut stream) = std::net::TcpStream::connect(&local_addr) {
             use std::io::Write;
             let _ = stream.write_all(b"POST /api/show_window HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
             std::thread::sleep(std::time::Duration::from_millis(100));
@@ -31,36 +51,20 @@ fn main() {
     }
 
     // Autostart configuration checking
-    std::thread::spawn(|| {
-        let is_flatpak = std::env::var("FLATPAK_ID").is_ok();
-        
-        let autostart_script = format!(
-            "mkdir -p ~/.config/autostart && echo '[Desktop Entry]\nType=Application\nName=Sound Blaster G6X Controller\nExec={} --minimized\nIcon=cc.dreamzone.SoundBlasterG6X\nTerminal=false\nStartupNotify=false\n' > ~/.config/autostart/cc.dreamzone.SoundBlasterG6X.desktop",
-            if is_flatpak { "flatpak run cc.dreamzone.SoundBlasterG6X" } else { "soundblaster-g6x" }
-        );
-        
-        if is_flatpak {
-            let _ = std::process::Command::new("flatpak-spawn")
-                .args(["--host", "bash", "-c", &autostart_script])
-                .output();
-        } else {
-            let _ = std::process::Command::new("bash")
-                .args(["-c", &autostart_script])
-                .output();
-        }
-    });
+    let autostart = config.autostart;
+    std::thread::spawn(move || config::set_autostart(autostart));
 
     // Set up event loop first to initialize GTK on Linux
     let event_loop = EventLoopBuilder::new().build();
 
-    tracing_subscriber::fmt()
-        .with_max_level(Level::DEBUG)
-        .init();
+    config::init_logging(&config);
 
     // Initialize device
     let device = BlasterXG6::init().expect("Failed to initialize device");
 
     // Spawn web server in a separate thread
+    // signals on `server_done` once it has shut down and flushed the profile
+    let (server_done_tx, server_done) = std::sync::mpsc::channel::<()>();
     std::thread::spawn(move || {
         let rt = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
@@ -70,6 +74,9 @@ fn main() {
         rt.block_on(async {
             server::start_server(device).await;
         });
+        let _ = server_done_tx.send(());
+        // SIGTERM / Ctrl+C end up here too, the window has to go as well
+        std::process::exit(0);
     });
 
     // Small delay to let the HTTP server start
@@ -88,7 +95,7 @@ fn main() {
     // Build WebView using GTK container from tao window (Linux-specific)
     let vbox = window.default_vbox().expect("Failed to get GTK vbox from tao window");
     let _webview = WebViewBuilder::new()
-        .with_url("http://127.0.0.1:3311")
+        .with_url(format!("http://{}", local_addr))
         .build_gtk(vbox)
         .unwrap();
 
@@ -147,7 +154,10 @@ fn main() {
                 window.set_focus();
                 window.request_user_attention(Some(tao::window::UserAttentionType::Critical));
             } else if event.id == quit_item.id() {
-                // Immediate clean exit, avoids waiting for background tasks to gracefully shutdown
+                // Give the server a moment to write pending profile changes,
+                // but don't hang around waiting for other background tasks
+                server::request_shutdown();
+                let _ = server_done.recv_timeout(std::time::Duration::from_secs(2));
                 std::process::exit(0);
             }
         }
@@ -188,3 +198,80 @@ fn load_window_icon() -> TaoIcon {
     let (rgba, width, height) = get_icon_image_data();
     TaoIcon::from_rgba(rgba, width, height).expect("Failed to create window icon")
 }
+
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    let idx = args.iter().position(|a| a == flag)?;
+    args.get(idx + 1).cloned()
+}
+
+/// Prints how an Equalizer APO config maps onto the G6 bands,
+/// and saves it as a profile if asked to
+fn import_apo(path: &str, save_as: Option<String>) -> ! {
+    let content = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        std::process::exit(1);
+    });
+    let import = apo::import(&content);
+
+    print_fit(&import.fit);
+    for skipped in &import.unsupported {
+        println!("Skipped line {}: {} ({})", skipped.line, skipped.text.trim(), skipped.reason);
+    }
+
+    if let Some(name) = save_as {
+        save_profile(&import.profile, &name);
+    }
+
+    std::process::exit(0);
+}
+
+/// Prints the bands of a measurement corrected towards a target curve,
+/// and saves them as a profile if asked to
+fn target_eq(path: &str, target: Option<String>, save_as: Option<String>) -> ! {
+    let measurement = target::Curve::load(std::path::Path::new(path)).unwrap_or_else(|e| {
+        eprintln!("Failed to read measurement: {}", e);
+        std::process::exit(1);
+    });
+    let target = target.unwrap_or(target::DEFAULT_TARGET.to_string());
+    let target_curve = target::load_target(&target).unwrap_or_else(|e| {
+        eprintln!("Failed to read target: {}", e);
+        std::process::exit(1);
+    });
+    let result = target::target_eq(&measurement, &target_curve);
+
+    println!("Target: {}", target);
+    print_fit(&result.fit);
+
+    if let Some(name) = save_as {
+        save_profile(&result.profile, &name);
+    }
+
+    std::process::exit(0);
+}
+
+fn print_fit(fit: &FitResult) {
+    println!("Pre-Amp: {:+.1} dB", fit.bands[0]);
+    for (freq, gain) in ["31Hz", "62Hz", "125Hz", "250Hz", "500Hz", "1kHz", "2kHz", "4kHz", "8kHz", "16kHz"].iter().zip(&fit.bands[1..]) {
+        println!("{:>6}: {:+.1} dB", freq, gain);
+    }
+    println!(
+        "Residual: {:.2} dB RMS, {:.2} dB max at {:.0} Hz",
+        fit.rms_error, fit.max_error, fit.max_error_freq
+    );
+}
+
+/// Saves a profile to the profile directory, exits on failure
+fn save_profile(profile: &Profile, name: &str) {
+    let saved = named_profile_path_in(&default_profile_dir(), name).and_then(|path| {
+        let json = serde_json::to_vec_pretty(profile)?;
+        write_atomic(&path, &json)?;
+        Ok(path)
+    });
+    match saved {
+        Ok(path) => println!("Saved profile {}", path.display()),
+        Err(e) => {
+            eprintln!("Failed to save profile {}: {}", name, e);
+            std::process::exit(1);
+        }
+    }
+}
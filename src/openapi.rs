@@ -0,0 +1,144 @@
+//! OpenAPI 3 document of the REST API, served at `/api/openapi.json`.
+//! Generated from the handlers in [`crate::api`] and the types they take
+//! and return. A test checks that it lists every route of the server.
+
+use axum::Json;
+use axum::response::IntoResponse;
+use utoipa::OpenApi;
+
+use crate::api;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Sound Blaster G6X Controller",
+        description = "Local REST API of the G6 controller, on 127.0.0.1"
+    ),
+    paths(
+        api::get_status,
+        api::events,
+        api::set_feature,
+        api::set_features,
+        api::reset,
+        api::get_history,
+        api::undo,
+        api::redo,
+        api::get_mixer,
+        api::set_mixer,
+        api::list_profiles,
+        api::apply_profile,
+        api::stack_profiles,
+        api::save_profile,
+        api::clone_profile,
+        api::get_app_rules,
+        api::set_app_rules,
+        api::get_streams,
+        api::get_schedules,
+        api::set_schedules,
+        api::export_share_code,
+        api::import_share_code,
+        api::get_autoeq_info,
+        api::load_autoeq,
+        api::reset_autoeq,
+        api::search_autoeq,
+        api::similar_autoeq,
+        api::apply_autoeq,
+        api::get_eq_response,
+        api::eq_response,
+        api::get_auto_preamp,
+        api::set_auto_preamp,
+        api::get_loudness,
+        api::set_loudness,
+        api::get_tone,
+        api::set_tone,
+        api::fit_eq,
+        api::import_apo,
+        api::get_targets,
+        api::target_eq,
+        api::export_pipewire,
+        api::export_easyeffects,
+        api::get_abx,
+        api::start_abx,
+        api::select_abx,
+        api::guess_abx,
+        api::stop_abx,
+        api::get_config,
+        api::set_config,
+    ),
+    tags(
+        (name = "device", description = "Features of the G6"),
+        (name = "events", description = "State changes as server-sent events"),
+        (name = "history", description = "Undo and redo"),
+        (name = "mixer", description = "Volumes and mutes"),
+        (name = "profiles", description = "Profiles and built-in presets"),
+        (name = "rules", description = "Profiles switched by application"),
+        (name = "schedules", description = "Profiles switched by time of day"),
+        (name = "share", description = "Share codes"),
+        (name = "autoeq", description = "AutoEq headphone corrections"),
+        (name = "eq", description = "EQ bands and what drives them"),
+        (name = "abx", description = "Blind comparison of two EQs"),
+        (name = "config", description = "Application settings"),
+    )
+)]
+pub struct ApiDoc;
+
+pub async fn openapi_json() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Not part of the REST API
+    const UNDOCUMENTED: [&str; 2] = ["/api/openapi.json", "/api/show_window"];
+
+    /// Path and method of every route of the `/api` router
+    fn routes() -> BTreeSet<(String, String)> {
+        crate::server::api_routes()
+            .routes
+            .into_iter()
+            .map(|(path, method)| (path.to_string(), method.to_string()))
+            .collect()
+    }
+
+    fn documented() -> BTreeSet<(String, String)> {
+        let mut documented = BTreeSet::new();
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            let methods = [
+                ("get", item.get.is_some()),
+                ("post", item.post.is_some()),
+                ("put", item.put.is_some()),
+                ("delete", item.delete.is_some()),
+                ("patch", item.patch.is_some()),
+            ];
+            for (method, present) in methods {
+                if present {
+                    documented.insert((path.clone(), method.to_string()));
+                }
+            }
+        }
+        documented
+    }
+
+    #[test]
+    fn every_route_is_documented() {
+        let routes: BTreeSet<_> = routes()
+            .into_iter()
+            .filter(|(path, _)| !UNDOCUMENTED.contains(&path.as_str()))
+            .collect();
+        assert!(routes.len() > 40, "no routes found in the router");
+        let documented = documented();
+        assert_eq!(
+            routes.difference(&documented).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "routes missing in the OpenAPI document"
+        );
+        assert_eq!(
+            documented.difference(&routes).collect::<Vec<_>>(),
+            Vec::<&(String, String)>::new(),
+            "documented paths the server doesn't have"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tracing::{debug, error};
+
+use crate::api::AppState;
+use crate::loudness;
+use crate::preamp;
+
+/// How long the state has to stay untouched before it's written to disk
+pub const SAVE_DEBOUNCE: Duration = Duration::from_millis(750);
+
+/// Upper bound for how long a save can be pushed back,
+/// so a slider that's dragged forever still gets persisted
+pub const SAVE_MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Debounces writes of `default.json`.
+/// Handlers only call [`ProfileSaver::request`],
+/// the actual write happens in the task started by [`spawn_saver`].
+#[derive(Default)]
+pub struct ProfileSaver {
+    dirty: AtomicBool,
+    notify: Notify,
+}
+
+impl ProfileSaver {
+    /// Marks the state as changed and (re)starts the debounce timer
+    pub fn request(&self) {
+        self.dirty.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+}
+
+/// Starts the background task that writes `default.json`
+/// once requests have settled for [`SAVE_DEBOUNCE`]
+pub fn spawn_saver(state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            state.saver.notify.notified().await;
+
+            let first_request = Instant::now();
+            loop {
+                let deadline = (Instant::now() + SAVE_DEBOUNCE)
+                    .min(first_request + SAVE_MAX_DELAY);
+                tokio::select! {
+                    _ = state.saver.notify.notified() => continue,
+                    _ = tokio::time::sleep_until(deadline) => break,
+                }
+            }
+
+            flush(&state).await;
+        }
+    })
+}
+
+/// Writes `default.json` right away if there are unsaved changes
+pub async fn flush(state: &AppState) {
+    if !state.saver.dirty.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    let device = state.device.lock().await;
+    if state.abx.lock().await.is_some() {
+        // A or B isn't the user's EQ, stopping the session requests a save
+        state.saver.dirty.store(true, Ordering::SeqCst);
+        return;
+    }
+    let default_profile = device.profile_path.join("default.json");
+    debug!("Saving default profile to {}", default_profile.display());
+    if let Err(e) = device.save_profile(default_profile) {
+        error!("Failed to save default profile: {}", e);
+        // keep it dirty, the next request or the shutdown flush retries
+        state.saver.dirty.store(true, Ordering::SeqCst);
+    }
+
+    // manual Pre-Amp edits change the auto Pre-Amp offset
+    let auto_preamp = preamp::auto_preamp_path(&device.profile_path);
+    if let Err(e) = device.auto_preamp.save(&auto_preamp) {
+        error!("Failed to save {}: {}", auto_preamp.display(), e);
+        state.saver.dirty.store(true, Ordering::SeqCst);
+    }
+
+    let loudness = loudness::loudness_path(&device.profile_path);
+    if let Err(e) = device.loudness.settings.save(&loudness) {
+        error!("Failed to save {}: {}", loudness.display(), e);
+        state.saver.dirty.store(true, Ordering::SeqCst);
+    }
+}
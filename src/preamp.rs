@@ -0,0 +1,161 @@
+//! Automatic anti-clipping Pre-Amp.
+//! Whenever the bands change the Pre-Amp is set so the peak of the EQ curve
+//! stays `headroom` below 0 dB.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+use crate::ISO_BANDS;
+use crate::eq::{self, MAX_FREQ, MAX_GAIN, MAX_RESPONSE_POINTS, MIN_FREQ};
+use crate::write_atomic;
+
+/// What a manual Pre-Amp edit does while auto mode is on
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ManualPreAmp {
+    /// The difference to the computed Pre-Amp is kept as an offset
+    #[default]
+    Offset,
+    /// Auto mode is switched off
+    Disable,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoPreAmp {
+    pub enabled: bool,
+    /// dB kept between the peak of the curve and 0 dB
+    pub headroom: f32,
+    pub manual: ManualPreAmp,
+    /// dB added to the computed Pre-Amp, from manual edits
+    pub offset: f32,
+    /// Set while something else owns the Pre-Amp, e.g. an ABX session
+    #[serde(skip)]
+    pub suspended: bool,
+}
+
+impl Default for AutoPreAmp {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            headroom: 1.0,
+            manual: ManualPreAmp::Offset,
+            offset: 0.0,
+            suspended: false,
+        }
+    }
+}
+
+/// The settings that can be changed, all optional
+#[derive(Deserialize, Clone, Debug, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AutoPreAmpUpdate {
+    pub enabled: Option<bool>,
+    pub headroom: Option<f32>,
+    pub manual: Option<ManualPreAmp>,
+    pub offset: Option<f32>,
+}
+
+impl AutoPreAmp {
+    /// Reads the settings, a missing file means the defaults
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        let settings: Self = serde_json::from_str(&content)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_vec_pretty(self)?;
+        write_atomic(path, &json)
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !(0.0..=MAX_GAIN).contains(&self.headroom) {
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Headroom must be within 0 and {} dB", MAX_GAIN),
+            )));
+        }
+        if !(-MAX_GAIN..=MAX_GAIN).contains(&self.offset) {
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Offset must be within ±{} dB", MAX_GAIN),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Applies the given settings, nothing changes if they are invalid
+    pub fn update(
+        &mut self,
+        update: &AutoPreAmpUpdate,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut settings = self.clone();
+        if let Some(enabled) = update.enabled {
+            settings.enabled = enabled;
+        }
+        if let Some(headroom) = update.headroom {
+            settings.headroom = headroom;
+        }
+        if let Some(manual) = update.manual {
+            settings.manual = manual;
+        }
+        if let Some(offset) = update.offset {
+            settings.offset = offset;
+        }
+        settings.validate()?;
+        *self = settings;
+        Ok(())
+    }
+
+    /// Enabled and not suspended
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.suspended
+    }
+
+    /// Pre-Amp that takes the peak of the bands down to `-headroom`,
+    /// without the offset
+    pub fn compensation(&self, gains: &[f32]) -> f32 {
+        let peak = eq::log_spaced(MAX_RESPONSE_POINTS, MIN_FREQ, MAX_FREQ)
+            .into_iter()
+            .chain(ISO_BANDS)
+            .map(|freq| eq::bands_response(gains, freq))
+            .fold(0.0, f64::max);
+        let preamp = -(peak + self.headroom as f64);
+        // 0.1 dB steps, rounded down so the peak never ends up above
+        (preamp * 10.0).floor() as f32 / 10.0
+    }
+
+    /// Pre-Amp for the bands, offset included
+    pub fn preamp(&self, gains: &[f32]) -> f32 {
+        let preamp = self.compensation(gains) + self.offset;
+        ((preamp * 10.0).round() / 10.0 + 0.0).clamp(-MAX_GAIN, MAX_GAIN)
+    }
+
+    /// Records a manual Pre-Amp edit to `value` while auto mode is on
+    pub fn manual_edit(&mut self, gains: &[f32], value: f32) {
+        match self.manual {
+            ManualPreAmp::Offset => {
+                self.offset = (value - self.compensation(gains))
+                    .clamp(-MAX_GAIN, MAX_GAIN);
+            }
+            ManualPreAmp::Disable => self.enabled = false,
+        }
+    }
+}
+
+/// The settings live next to the profile directory, not inside it,
+/// so they don't show up as a profile
+pub fn auto_preamp_path(profile_path: &Path) -> PathBuf {
+    profile_path
+        .parent()
+        .unwrap_or(profile_path)
+        .join("auto_preamp.json")
+}
@@ -0,0 +1,137 @@
+//! Built-in presets, bundled profile documents that can't be changed.
+//! They're named `builtin/<name>`, which no profile file can be called,
+//! and are applied like any other profile.
+
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use crate::named_profile_path_in;
+use crate::profile::Profile;
+
+/// Prefix of the preset names, `/` is not allowed in profile names
+pub const PREFIX: &str = "builtin/";
+
+/// Bundled presets, by name without the prefix
+const PRESETS: &[(&str, &str)] = &[
+    ("Flat", include_str!("../data/presets/flat.json")),
+    (
+        "Bass Boost",
+        include_str!("../data/presets/bass_boost.json"),
+    ),
+    ("Vocal", include_str!("../data/presets/vocal.json")),
+    (
+        "Gaming Footsteps",
+        include_str!("../data/presets/gaming_footsteps.json"),
+    ),
+    ("Gaming", include_str!("../data/presets/gaming.json")),
+    ("Acoustic", include_str!("../data/presets/acoustic.json")),
+    ("Classical", include_str!("../data/presets/classical.json")),
+    ("EDM", include_str!("../data/presets/edm.json")),
+    ("Hip-Hop", include_str!("../data/presets/hip_hop.json")),
+    ("Jazz", include_str!("../data/presets/jazz.json")),
+    ("Pop", include_str!("../data/presets/pop.json")),
+    ("Rock", include_str!("../data/presets/rock.json")),
+];
+
+/// The presets, parsed on first use. They are part of the build,
+/// one that doesn't parse is a bug and must not just go missing.
+static PARSED: LazyLock<Vec<(&str, Profile)>> = LazyLock::new(|| {
+    PRESETS
+        .iter()
+        .map(|(name, content)| {
+            let profile = Profile::parse(content).unwrap_or_else(|e| {
+                panic!("Bundled preset {} is invalid: {}", name, e)
+            });
+            (*name, profile)
+        })
+        .collect()
+});
+
+/// Names of all presets, with the prefix
+pub fn names() -> Vec<String> {
+    PRESETS
+        .iter()
+        .map(|(name, _)| format!("{}{}", PREFIX, name))
+        .collect()
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    name.starts_with(PREFIX)
+}
+
+/// A preset by its name with the prefix
+pub fn preset(name: &str) -> Option<Profile> {
+    let name = name.strip_prefix(PREFIX)?;
+    PARSED
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, profile)| profile.clone())
+}
+
+/// A preset or a profile from `profile_dir`, as stored.
+/// Tone controls aren't resolved, see [`crate::tone::resolve`].
+pub fn load_named(
+    profile_dir: &Path,
+    name: &str,
+) -> Result<Profile, Box<dyn Error>> {
+    if is_builtin(name) {
+        return preset(name).ok_or_else(|| {
+            Box::new(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("Preset {} not found", name),
+            ))
+            .into()
+        });
+    }
+
+    let path = named_profile_path_in(profile_dir, name)?;
+    if !path.exists() {
+        return Err(Box::new(std::io::Error::new(
+            ErrorKind::NotFound,
+            format!("Profile {} not found", name),
+        )));
+    }
+    Profile::load(&path)
+}
+
+/// Checks that `name` refers to a preset or is a valid profile name,
+/// for settings that apply a profile later
+pub fn validate_name(
+    profile_dir: &Path,
+    name: &str,
+) -> Result<(), Box<dyn Error>> {
+    if is_builtin(name) {
+        if preset(name).is_none() {
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown preset: {}", name),
+            )));
+        }
+        return Ok(());
+    }
+    named_profile_path_in(profile_dir, name).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Layer;
+
+    #[test]
+    fn every_preset_parses_with_an_eq() {
+        for name in names() {
+            let profile = preset(&name).unwrap();
+            assert!(profile.layers().contains(&Layer::Eq), "{}", name);
+        }
+        assert_eq!(names().len(), PRESETS.len());
+    }
+
+    #[test]
+    fn only_prefixed_names_are_presets() {
+        assert!(preset("builtin/Flat").is_some());
+        assert!(preset("Flat").is_none());
+        assert!(preset("builtin/Missing").is_none());
+    }
+}
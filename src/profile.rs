@@ -0,0 +1,340 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::path::Path;
+use utoipa::ToSchema;
+
+use crate::api::MixerResponse;
+use crate::tone::ToneControls;
+use crate::{FEATURES, Feature, FeatureType, Format};
+
+/// A part of the device state that can be saved and applied on its own.
+/// Profiles can contain any combination of layers,
+/// so e.g. the EQ of one profile can be stacked on the effects of another.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    /// SBX and Scout Mode with all their effects
+    Effects,
+    /// Output routing (headphones / speakers)
+    Routing,
+    /// Equalizer toggle, Pre-Amp and the 10 bands
+    Eq,
+    /// Volumes and mutes of the ALSA / PulseAudio mixer
+    Mixer,
+}
+
+impl Layer {
+    /// All layers, in the order they get applied.
+    /// EQ comes after Effects, because EQ sliders switch SBX on.
+    pub const ALL: [Layer; 4] =
+        [Layer::Effects, Layer::Routing, Layer::Eq, Layer::Mixer];
+
+    /// The layer a feature belongs to
+    pub fn of(feature: &Feature) -> Layer {
+        if feature.name == "Equalizer" || feature.name.starts_with("EQ ") {
+            Layer::Eq
+        } else if matches!(feature.id, Format::Routing(_)) {
+            Layer::Routing
+        } else {
+            Layer::Effects
+        }
+    }
+
+    /// All features of this layer, with their default values.
+    /// Empty for the Mixer, which isn't made of HID features.
+    pub fn features(self) -> impl Iterator<Item = &'static Feature> {
+        FEATURES.iter().filter(move |f| Layer::of(f) == self)
+    }
+}
+
+impl Display for Layer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Layer::Effects => "effects",
+            Layer::Routing => "routing",
+            Layer::Eq => "eq",
+            Layer::Mixer => "mixer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A profile made of layers.
+/// Feature layers only list features that differ from their default,
+/// everything else in a present layer is reset when it's applied.
+/// Layers that are missing leave the device untouched.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effects: Option<Vec<Feature>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routing: Option<Vec<Feature>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eq: Option<Vec<Feature>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mixer: Option<BTreeMap<String, MixerResponse>>,
+    /// Tone controls that make up the EQ layer instead of stored bands,
+    /// see [`crate::tone::resolve`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tone: Option<ToneControls>,
+}
+
+impl Profile {
+    /// Splits a flat feature list (the old profile format) into its layers.
+    /// Every feature layer is present, so applying it restores the full state.
+    pub fn from_features(features: &[Feature]) -> Self {
+        let layer = |layer: Layer| {
+            Some(
+                features
+                    .iter()
+                    .filter(|f| Layer::of(f) == layer)
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        Self {
+            effects: layer(Layer::Effects),
+            routing: layer(Layer::Routing),
+            eq: layer(Layer::Eq),
+            mixer: None,
+            tone: None,
+        }
+    }
+
+    /// A profile with only the EQ layer: Equalizer on, Pre-Amp and bands
+    /// in the layout of [`crate::BlasterXG6::get_ten_band_eq`]
+    pub fn from_ten_band_eq(bands: [f32; 11]) -> Self {
+        let mut sliders = bands.into_iter();
+        let eq = Layer::Eq
+            .features()
+            .filter_map(|default| {
+                let mut feature = default.clone();
+                feature.value = match default.value {
+                    FeatureType::Toggle(_) => FeatureType::Toggle(true),
+                    FeatureType::Slider(_) => {
+                        let value = sliders.next().unwrap_or(0.0);
+                        if value == 0.0 {
+                            return None;
+                        }
+                        FeatureType::Slider(value.clamp(-12.0, 12.0))
+                    }
+                };
+                Some(feature)
+            })
+            .collect();
+
+        Self {
+            eq: Some(eq),
+            ..Default::default()
+        }
+    }
+
+    /// Pre-Amp and bands of the EQ layer, in the layout of
+    /// [`crate::BlasterXG6::get_ten_band_eq`]. `None` without an EQ layer.
+    pub fn ten_band_eq(&self) -> Option<[f32; 11]> {
+        let targets = self.layer_targets(Layer::Eq)?;
+        let mut bands = [0.0; 11];
+        let sliders = targets.iter().filter_map(|f| f.value.as_f32());
+        for (band, value) in bands.iter_mut().zip(sliders) {
+            *band = value;
+        }
+        Some(bands)
+    }
+
+    /// Reads a profile, either in the layered or in the old flat format
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&content)
+    }
+
+    /// Parses a profile, either in the layered or in the old flat format.
+    /// Features listed in the wrong layer are rejected.
+    pub fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+        let value: serde_json::Value = serde_json::from_str(content)?;
+
+        let profile = if value.is_array() {
+            let features: Vec<Feature> = serde_json::from_value(value)?;
+            Self::from_features(&features)
+        } else {
+            serde_json::from_value(value)?
+        };
+
+        for layer in Layer::ALL {
+            for feature in profile.layer_features(layer).unwrap_or_default() {
+                if Layer::of(feature) != layer {
+                    return Err(Box::new(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Feature {} does not belong to the {} layer",
+                            feature.name, layer
+                        ),
+                    )));
+                }
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// The layers this profile contains
+    pub fn layers(&self) -> Vec<Layer> {
+        Layer::ALL
+            .into_iter()
+            .filter(|layer| match layer {
+                Layer::Mixer => self.mixer.is_some(),
+                Layer::Eq => self.eq.is_some() || self.tone.is_some(),
+                _ => self.layer_features(*layer).is_some(),
+            })
+            .collect()
+    }
+
+    /// The features stored for a feature layer, `None` if the layer is absent
+    pub fn layer_features(&self, layer: Layer) -> Option<&[Feature]> {
+        match layer {
+            Layer::Effects => self.effects.as_deref(),
+            Layer::Routing => self.routing.as_deref(),
+            Layer::Eq => self.eq.as_deref(),
+            Layer::Mixer => None,
+        }
+    }
+
+    pub fn layer_features_mut(
+        &mut self,
+        layer: Layer,
+    ) -> Option<&mut Option<Vec<Feature>>> {
+        match layer {
+            Layer::Effects => Some(&mut self.effects),
+            Layer::Routing => Some(&mut self.routing),
+            Layer::Eq => Some(&mut self.eq),
+            Layer::Mixer => None,
+        }
+    }
+
+    /// Keeps only the given layers
+    pub fn retain_layers(&mut self, layers: &[Layer]) {
+        for layer in Layer::ALL {
+            if layers.contains(&layer) {
+                continue;
+            }
+            if layer == Layer::Eq {
+                self.tone = None;
+            }
+            match self.layer_features_mut(layer) {
+                Some(features) => *features = None,
+                None => self.mixer = None,
+            }
+        }
+    }
+
+    /// The full target state of a feature layer:
+    /// every feature of the layer, either with its stored or its default value
+    pub fn layer_targets(&self, layer: Layer) -> Option<Vec<Feature>> {
+        let stored = self.layer_features(layer)?;
+        Some(
+            layer
+                .features()
+                .map(|default| {
+                    stored
+                        .iter()
+                        .find(|f| f.name == default.name)
+                        .unwrap_or(default)
+                        .clone()
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Which profile a layer currently on the device came from
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct ActiveLayer {
+    pub profile: String,
+    /// Set once a feature of the layer got changed after the profile was applied
+    pub modified: bool,
+}
+
+/// The state of some layers, taken before a profile got applied temporarily
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub profile: Profile,
+    pub active_layers: BTreeMap<Layer, ActiveLayer>,
+}
+
+/// Identifies a profile applied with
+/// [`crate::BlasterXG6::apply_temporarily`]
+pub type OverrideId = u64;
+
+/// Profiles applied temporarily by app rules and schedules, oldest first,
+/// and the state from before the first of them took over.
+/// There's a single base for all of them, so ending one override never
+/// brings back the state another one replaced.
+#[derive(Default)]
+pub struct Overrides {
+    /// `None` while no override is applied
+    pub base: Option<Snapshot>,
+    pub applied: Vec<(OverrideId, String, Profile)>,
+    next_id: OverrideId,
+}
+
+impl Overrides {
+    pub fn next_id(&mut self) -> OverrideId {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Adds the layers of `current` that aren't in the base yet,
+    /// layers already there keep their state from before the first override
+    pub fn extend_base(
+        &mut self,
+        current: Profile,
+        active_layers: &BTreeMap<Layer, ActiveLayer>,
+    ) {
+        let base = self.base.get_or_insert_with(|| Snapshot {
+            profile: Profile::default(),
+            active_layers: BTreeMap::new(),
+        });
+        let known = base.profile.layers();
+        for layer in current.layers() {
+            if known.contains(&layer) {
+                continue;
+            }
+            match layer {
+                Layer::Effects => {
+                    base.profile.effects = current.effects.clone()
+                }
+                Layer::Routing => {
+                    base.profile.routing = current.routing.clone()
+                }
+                Layer::Eq => {
+                    base.profile.eq = current.eq.clone();
+                    base.profile.tone = current.tone.clone();
+                }
+                Layer::Mixer => base.profile.mixer = current.mixer.clone(),
+            }
+            match active_layers.get(&layer) {
+                Some(active) => {
+                    base.active_layers.insert(layer, active.clone());
+                }
+                None => {
+                    base.active_layers.remove(&layer);
+                }
+            }
+        }
+    }
+}
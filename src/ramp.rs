@@ -0,0 +1,142 @@
+//! Smooth EQ transitions when switching profiles.
+//! Pre-Amp and bands move to the new values in small steps,
+//! so large jumps on the bass bands don't thump.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::api::AppState;
+use crate::eq::MAX_GAIN;
+use crate::profile::{Layer, Profile};
+use crate::{BlasterXG6, Feature, FeatureType};
+
+/// Time between two steps of a ramp, each step writes up to 11 sliders
+pub const RAMP_STEP: Duration = Duration::from_millis(25);
+
+/// Longest allowed ramp
+pub const MAX_RAMP: Duration = Duration::from_secs(10);
+
+/// Hands out generations, a ramp stops once a newer one exists
+#[derive(Default)]
+pub struct Ramp {
+    generation: AtomicU64,
+}
+
+impl Ramp {
+    /// Starts a new switch, which cancels any ramp in flight
+    pub fn start(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn is_current(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) == generation
+    }
+}
+
+/// Pre-Amp and bands once `profiles` are applied on top of the current
+/// state. `None` if the Equalizer isn't on before and after,
+/// there is nothing to hear then.
+pub fn eq_target(
+    device: &BlasterXG6,
+    profiles: &[Profile],
+) -> Option<[f32; 11]> {
+    let is_on = |features: &[Feature], name: &str| {
+        features
+            .iter()
+            .find(|f| f.name == name)
+            .and_then(|f| f.value.as_bool())
+    };
+    let mut features = device.features.clone();
+    if is_on(&features, "Equalizer") != Some(true) {
+        return None;
+    }
+
+    for profile in profiles {
+        for target in profile.layer_targets(Layer::Eq).into_iter().flatten() {
+            if let Some(feature) =
+                features.iter_mut().find(|f| f.name == target.name)
+            {
+                feature.value = target.value;
+            }
+        }
+    }
+    if is_on(&features, "Equalizer") != Some(true) {
+        return None;
+    }
+
+    let mut bands = device.get_ten_band_eq()?;
+    for (band, name) in bands.iter_mut().zip(crate::ten_band_eq_names()) {
+        if let Some(FeatureType::Slider(value)) = features
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| f.value.clone())
+        {
+            *band = value;
+        }
+    }
+    if device.auto_preamp.is_active() {
+        bands[0] = device
+            .auto_preamp
+            .preamp(&device.compensated_gains(&bands[1..]));
+    }
+    Some(bands)
+}
+
+/// Band sets between `from` and `to`, one per [`RAMP_STEP`] of `duration`.
+/// `to` is the last one, `from` isn't included.
+pub fn steps(
+    from: &[f32; 11],
+    to: &[f32; 11],
+    duration: Duration,
+) -> Vec<[f32; 11]> {
+    let count = (duration.as_millis() / RAMP_STEP.as_millis()).max(1) as usize;
+    (1..=count)
+        .map(|step| {
+            let t = step as f32 / count as f32;
+            let mut bands = [0.0; 11];
+            for ((band, from), to) in bands.iter_mut().zip(from).zip(to) {
+                // the device takes 0.1 dB steps
+                *band = ((from + (to - from) * t) * 10.0).round() / 10.0;
+                *band = band.clamp(-MAX_GAIN, MAX_GAIN);
+            }
+            bands
+        })
+        .collect()
+}
+
+/// Moves Pre-Amp and bands to `target` over `duration`.
+/// The device is only locked for each step, not in between.
+/// Returns `false` if a newer switch took over.
+pub async fn ramp_eq(
+    state: &AppState,
+    target: [f32; 11],
+    duration: Duration,
+    generation: u64,
+) -> bool {
+    let Some(from) = state.device.lock().await.get_ten_band_eq() else {
+        return state.ramp.is_current(generation);
+    };
+    if from == target {
+        return state.ramp.is_current(generation);
+    }
+
+    for bands in steps(&from, &target, duration.min(MAX_RAMP)) {
+        if !state.ramp.is_current(generation) {
+            return false;
+        }
+        let written = state
+            .device
+            .lock()
+            .await
+            .write_ten_band_eq(&bands)
+            .map_err(|e| e.to_string());
+        if let Err(e) = written {
+            // the switch itself still sets the final values
+            warn!("EQ ramp failed: {}", e);
+            break;
+        }
+        tokio::time::sleep(RAMP_STEP).await;
+    }
+    state.ramp.is_current(generation)
+}
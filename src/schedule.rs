@@ -0,0 +1,226 @@
+use chrono::Timelike;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::api::AppState;
+use crate::profile::{Layer, OverrideId};
+use crate::write_atomic;
+
+/// How often the schedules are checked against the clock
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(20);
+
+/// A time of day with minute resolution, written as `"HH:MM"`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay {
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl TimeOfDay {
+    pub fn now() -> Self {
+        let now = chrono::Local::now();
+        Self {
+            hour: now.hour() as u8,
+            minute: now.minute() as u8,
+        }
+    }
+
+    fn minutes(self) -> u16 {
+        self.hour as u16 * 60 + self.minute as u16
+    }
+}
+
+impl std::str::FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid time {:?}, expected HH:MM", s);
+        let (hour, minute) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let hour: u8 = hour.parse().map_err(|_| invalid())?;
+        let minute: u8 = minute.parse().map_err(|_| invalid())?;
+        if hour > 23 || minute > 59 {
+            return Err(invalid());
+        }
+        Ok(Self { hour, minute })
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+impl Serialize for TimeOfDay {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Applies a profile from `start` until `end`, then goes back to what was
+/// there before. Windows may wrap around midnight (22:00 - 08:00).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct Schedule {
+    /// `HH:MM`
+    #[schema(value_type = String, example = "22:00")]
+    pub start: TimeOfDay,
+    #[schema(value_type = String, example = "08:00")]
+    pub end: TimeOfDay,
+    /// Name of the profile to apply
+    pub profile: String,
+    /// Layers to take from the profile, all of them if omitted
+    #[serde(default)]
+    pub layers: Option<Vec<Layer>>,
+}
+
+impl Schedule {
+    pub fn is_active(&self, now: TimeOfDay) -> bool {
+        let (start, end, now) =
+            (self.start.minutes(), self.end.minutes(), now.minutes());
+        if start < end {
+            start <= now && now < end
+        } else {
+            now >= start || now < end
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.start == self.end {
+            return Err(Box::new(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Schedule for profile {} starts and ends at {}",
+                    self.profile, self.start
+                ),
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A schedule whose window is open right now
+struct EngagedSchedule {
+    schedule: Schedule,
+    /// `None` if applying the profile failed, then there's nothing to revert
+    applied: Option<OverrideId>,
+}
+
+/// Time of day schedules.
+/// The scheduler only acts when a window opens or closes, so manual changes
+/// in between stick until the next boundary.
+#[derive(Default)]
+pub struct Scheduler {
+    pub schedules: Vec<Schedule>,
+    engaged: Vec<EngagedSchedule>,
+}
+
+impl Scheduler {
+    /// Reads the schedules, a missing file means no schedules
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        let schedules: Vec<Schedule> = serde_json::from_str(&content)?;
+        for schedule in &schedules {
+            schedule.validate()?;
+        }
+        Ok(Self {
+            schedules,
+            engaged: Vec::new(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_vec_pretty(&self.schedules)?;
+        write_atomic(path, &json)
+    }
+
+    /// The schedules whose window is open right now
+    pub fn engaged(&self) -> Vec<Schedule> {
+        self.engaged.iter().map(|e| e.schedule.clone()).collect()
+    }
+}
+
+/// The schedules live next to the profile directory, not inside it,
+/// so they don't show up as a profile
+pub fn schedules_path(profile_path: &Path) -> PathBuf {
+    profile_path
+        .parent()
+        .unwrap_or(profile_path)
+        .join("schedules.json")
+}
+
+/// Starts the task that opens and closes schedule windows
+pub fn spawn_scheduler(state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            check_schedules(&state, TimeOfDay::now()).await;
+        }
+    })
+}
+
+async fn check_schedules(state: &AppState, now: TimeOfDay) {
+    let mut scheduler = state.scheduler.lock().await;
+    let Scheduler { schedules, engaged } = &mut *scheduler;
+
+    let closing = |e: &mut EngagedSchedule| {
+        !e.schedule.is_active(now) || !schedules.contains(&e.schedule)
+    };
+    let opened: Vec<Schedule> = schedules
+        .iter()
+        .filter(|s| s.is_active(now))
+        .filter(|s| !engaged.iter().any(|e| &e.schedule == *s))
+        .cloned()
+        .collect();
+
+    if !engaged.iter_mut().any(closing) && opened.is_empty() {
+        return;
+    }
+
+    // takes over from a profile switch that is still ramping
+    state.ramp.start();
+    let mut device = state.device.lock().await;
+    if state.abx.lock().await.is_some() {
+        // the session owns the EQ, windows open and close once it's stopped
+        return;
+    }
+    let closed: Vec<EngagedSchedule> =
+        engaged.extract_if(.., closing).collect();
+
+    for closing in closed {
+        info!("Schedule for profile {} ended", closing.schedule.profile);
+        if let Some(applied) = closing.applied
+            && let Err(e) = device.revert_temporary(applied)
+        {
+            warn!("Failed to revert scheduled profile: {}", e);
+        }
+    }
+
+    for schedule in opened {
+        info!("Schedule for profile {} started", schedule.profile);
+        let applied = device
+            .apply_temporarily(&schedule.profile, schedule.layers.as_deref())
+            .inspect_err(|e| {
+                warn!("Failed to apply profile {}: {}", schedule.profile, e);
+            })
+            .ok();
+        engaged.push(EngagedSchedule { schedule, applied });
+    }
+}
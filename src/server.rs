@@ -2,21 +2,56 @@ use axum::{
     body::Body,
     extract::{State, Request},
     http::{StatusCode, header, Uri},
+    handler::Handler,
     response::{IntoResponse, Response},
-    routing::{get, post},
+    routing::{get, post, put, MethodRouter},
     Router,
 };
 use rust_embed::RustEmbed;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 use tower_http::cors::CorsLayer;
 
 use crate::api::{self, AppState};
+use crate::autoeq;
+use crate::autoswitch::{self, AutoSwitch};
+use crate::config;
+use crate::events;
+use crate::history::History;
+use crate::loudness::{self, LoudnessSettings};
+use crate::openapi;
+use crate::persist::{self, ProfileSaver};
+use crate::preamp::{self, AutoPreAmp};
+use crate::ramp::Ramp;
+use crate::schedule::{self, Scheduler};
+use crate::watch;
 use crate::BlasterXG6;
 
 pub static SHOW_WINDOW_REQUEST: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
 
+/// Stops the server and flushes unsaved state, see [`request_shutdown`]
+static SHUTDOWN_REQUEST: Notify = Notify::const_new();
+
+/// Asks [`start_server`] to stop serving and write pending changes to disk.
+/// `start_server` returns once that's done.
+pub fn request_shutdown() {
+    SHUTDOWN_REQUEST.notify_one();
+}
+
+async fn shutdown_signal() {
+    let mut sigterm = tokio::signal::unix::signal(
+        tokio::signal::unix::SignalKind::terminate(),
+    )
+    .expect("Failed to install SIGTERM handler");
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = sigterm.recv() => {},
+        _ = SHUTDOWN_REQUEST.notified() => {},
+    }
+}
+
 pub async fn show_window() -> impl IntoResponse {
     SHOW_WINDOW_REQUEST.store(true, std::sync::atomic::Ordering::Relaxed);
     StatusCode::OK
@@ -26,26 +61,160 @@ pub async fn show_window() -> impl IntoResponse {
 #[folder = "frontend/build/"]
 pub struct Assets;
 
-pub async fn start_server(device: BlasterXG6) {
+/// The `/api` router, with the path and method of each of its routes,
+/// which the OpenAPI document is checked against
+pub struct ApiRoutes {
+    pub router: Router<Arc<AppState>>,
+    pub routes: Vec<(&'static str, &'static str)>,
+}
+
+impl ApiRoutes {
+    fn add(mut self, path: &'static str, method: &'static str, method_router: MethodRouter<Arc<AppState>>) -> Self {
+        // methods on the same path are merged by axum
+        self.router = self.router.route(path, method_router);
+        self.routes.push((path, method));
+        self
+    }
+
+    fn get<H: Handler<T, Arc<AppState>>, T: 'static>(self, path: &'static str, handler: H) -> Self {
+        self.add(path, "get", get(handler))
+    }
+
+    fn post<H: Handler<T, Arc<AppState>>, T: 'static>(self, path: &'static str, handler: H) -> Self {
+        self.add(path, "post", post(handler))
+    }
+
+    fn put<H: Handler<T, Arc<AppState>>, T: 'static>(self, path: &'static str, handler: H) -> Self {
+        self.add(path, "put", put(handler))
+    }
+}
+
+pub fn api_routes() -> ApiRoutes {
+    ApiRoutes { router: Router::new(), routes: Vec::new() }
+        .get("/api/status", api::get_status)
+        .get("/api/events", api::events)
+        .get("/api/openapi.json", openapi::openapi_json)
+        .post("/api/feature", api::set_feature)
+        .post("/api/features", api::set_features)
+        .post("/api/reset", api::reset)
+        .get("/api/history", api::get_history)
+        .post("/api/undo", api::undo)
+        .post("/api/redo", api::redo)
+        .get("/api/mixer/status", api::get_mixer)
+        .post("/api/mixer/feature", api::set_mixer)
+        .get("/api/profiles", api::list_profiles)
+        .post("/api/profile/apply", api::apply_profile)
+        .post("/api/profile/stack", api::stack_profiles)
+        .post("/api/profile/save", api::save_profile)
+        .post("/api/profile/clone", api::clone_profile)
+        .get("/api/rules", api::get_app_rules)
+        .put("/api/rules", api::set_app_rules)
+        .get("/api/rules/streams", api::get_streams)
+        .get("/api/schedules", api::get_schedules)
+        .put("/api/schedules", api::set_schedules)
+        .get("/api/share", api::export_share_code)
+        .post("/api/share/import", api::import_share_code)
+        .get("/api/autoeq", api::get_autoeq_info)
+        .post("/api/autoeq/load", api::load_autoeq)
+        .post("/api/autoeq/reset", api::reset_autoeq)
+        .get("/api/autoeq/search", api::search_autoeq)
+        .get("/api/autoeq/similar", api::similar_autoeq)
+        .post("/api/autoeq/apply", api::apply_autoeq)
+        .get("/api/eq/response", api::get_eq_response)
+        .post("/api/eq/response", api::eq_response)
+        .get("/api/eq/auto_preamp", api::get_auto_preamp)
+        .put("/api/eq/auto_preamp", api::set_auto_preamp)
+        .get("/api/eq/loudness", api::get_loudness)
+        .put("/api/eq/loudness", api::set_loudness)
+        .get("/api/eq/tone", api::get_tone)
+        .put("/api/eq/tone", api::set_tone)
+        .post("/api/eq/fit", api::fit_eq)
+        .post("/api/eq/import/apo", api::import_apo)
+        .get("/api/eq/targets", api::get_targets)
+        .post("/api/eq/target", api::target_eq)
+        .get("/api/eq/export/pipewire", api::export_pipewire)
+        .get("/api/eq/export/easyeffects", api::export_easyeffects)
+        .get("/api/abx", api::get_abx)
+        .post("/api/abx/start", api::start_abx)
+        .post("/api/abx/select", api::select_abx)
+        .post("/api/abx/guess", api::guess_abx)
+        .post("/api/abx/stop", api::stop_abx)
+        .get("/api/config", api::get_config)
+        .put("/api/config", api::set_config)
+        .post("/api/show_window", show_window)
+}
+
+pub async fn start_server(mut device: BlasterXG6) {
+    if let Some(path) = config::get().autoeq_path {
+        let mode = config::get().autoeq_mode;
+        match tokio::task::spawn_blocking(move || autoeq::load(&path, mode).map_err(|e| e.to_string())).await {
+            Ok(Ok(info)) => tracing::info!("Loaded {} AutoEq measurements", info.measurements),
+            Ok(Err(e)) => tracing::error!("Failed to load AutoEq results: {}", e),
+            Err(e) => tracing::error!("Failed to load AutoEq results: {}", e),
+        }
+    }
+
+    let auto_preamp_path = preamp::auto_preamp_path(&device.profile_path);
+    device.auto_preamp = AutoPreAmp::load(&auto_preamp_path).unwrap_or_else(|e| {
+        tracing::error!("Failed to load {}: {}", auto_preamp_path.display(), e);
+        AutoPreAmp::default()
+    });
+    let loudness_path = loudness::loudness_path(&device.profile_path);
+    device.loudness.settings = LoudnessSettings::load(&loudness_path).unwrap_or_else(|e| {
+        tracing::error!("Failed to load {}: {}", loudness_path.display(), e);
+        LoudnessSettings::default()
+    });
+
+    let rules_path = autoswitch::rules_path(&device.profile_path);
+    let autoswitch = AutoSwitch::load(&rules_path).unwrap_or_else(|e| {
+        tracing::error!("Failed to load {}: {}", rules_path.display(), e);
+        AutoSwitch::default()
+    });
+    let profile_path = device.profile_path.clone();
+    let schedules_path = schedule::schedules_path(&device.profile_path);
+    let scheduler = Scheduler::load(&schedules_path).unwrap_or_else(|e| {
+        tracing::error!("Failed to load {}: {}", schedules_path.display(), e);
+        Scheduler::default()
+    });
+
+    let events = device.events.clone();
     let shared_state = Arc::new(AppState {
         device: Mutex::new(device),
+        saver: ProfileSaver::default(),
+        autoswitch: Mutex::new(autoswitch),
+        scheduler: Mutex::new(scheduler),
+        history: Mutex::new(History::default()),
+        profile_errors: Mutex::new(Default::default()),
+        ramp: Ramp::default(),
+        abx: Mutex::new(None),
+        events,
     });
 
-    let app = Router::new()
-        .route("/api/status", get(api::get_status))
-        .route("/api/feature", post(api::set_feature))
-        .route("/api/mixer/status", get(api::get_mixer))
-        .route("/api/mixer/feature", post(api::set_mixer))
-        .route("/api/show_window", post(show_window))
+    persist::spawn_saver(shared_state.clone());
+    autoswitch::spawn_watcher(shared_state.clone());
+    schedule::spawn_scheduler(shared_state.clone());
+    events::spawn_watcher(shared_state.clone());
+    if let Err(e) = watch::spawn_profile_watcher(shared_state.clone(), profile_path) {
+        tracing::error!("Failed to watch the profile directory: {}", e);
+    }
+
+    let app = api_routes()
+        .router
         .fallback(static_handler)
-        .with_state(shared_state)
+        .with_state(shared_state.clone())
         .layer(CorsLayer::permissive());
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3311));
+    let addr = SocketAddr::from(([127, 0, 0, 1], config::get().port));
     println!("Web server listening on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    tokio::select! {
+        result = axum::serve(listener, app) => result.unwrap(),
+        _ = shutdown_signal() => println!("Shutting down web server"),
+    }
+
+    // don't lose the last few slider ticks
+    persist::flush(&shared_state).await;
 }
 
 async fn static_handler(uri: Uri) -> impl IntoResponse {
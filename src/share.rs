@@ -0,0 +1,321 @@
+//! Compact share codes for profiles, e.g. `G6-AQdAAEGAAAA5AN4ydjk`
+//!
+//! ### Layout (version 1)
+//! - 1 Byte version
+//! - 1 Byte layer mask (Effects, Routing, Eq)
+//! - 2 Bytes toggles that are on, one bit per toggle in [`FEATURES`] order
+//! - 4 Bytes sliders that aren't 0, one bit per slider in [`FEATURES`] order
+//! - per set slider: EQ as i8 in 0.1 dB, everything else as u16 in 0.01
+//! - 2 Bytes CRC-16 of everything before
+//!
+//! All little endian, base64url encoded without padding.
+//! The bit positions depend on the order of [`FEATURES`],
+//! so any change to it needs a new version.
+
+use std::error::Error;
+use std::io::ErrorKind;
+
+use crate::profile::{Layer, Profile};
+use crate::{FEATURES, Feature, FeatureType, Format};
+
+pub const SHARE_CODE_VERSION: u8 = 1;
+pub const SHARE_CODE_PREFIX: &str = "G6-";
+
+/// Layers a share code can carry, the mixer is system specific
+const SHARED_LAYERS: [Layer; 3] = [Layer::Effects, Layer::Routing, Layer::Eq];
+
+const BASE64_URL: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn invalid(message: impl Into<String>) -> Box<dyn Error> {
+    Box::new(std::io::Error::new(ErrorKind::InvalidData, message.into()))
+}
+
+fn toggles() -> impl Iterator<Item = &'static Feature> {
+    FEATURES
+        .iter()
+        .filter(|f| matches!(f.value, FeatureType::Toggle(_)))
+}
+
+fn sliders() -> impl Iterator<Item = &'static Feature> {
+    FEATURES
+        .iter()
+        .filter(|f| matches!(f.value, FeatureType::Slider(_)))
+}
+
+/// EQ sliders are in dB, all others in 0-100
+fn is_eq_slider(feature: &Feature) -> bool {
+    matches!(feature.id, Format::SBX(id) if (0x0a..=0x14).contains(&id))
+}
+
+/// Encodes the feature layers of a profile into a share code
+pub fn encode(profile: &Profile) -> String {
+    let mut layer_mask = 0u8;
+    let mut features: Vec<&Feature> = Vec::new();
+    for (bit, layer) in SHARED_LAYERS.iter().enumerate() {
+        if let Some(layer_features) = profile.layer_features(*layer) {
+            layer_mask |= 1 << bit;
+            features.extend(layer_features);
+        }
+    }
+    let value_of =
+        |name: &str| features.iter().find(|f| f.name == name).map(|f| &f.value);
+
+    let mut toggle_bits = 0u16;
+    for (bit, toggle) in toggles().enumerate() {
+        if let Some(FeatureType::Toggle(true)) = value_of(toggle.name) {
+            toggle_bits |= 1 << bit;
+        }
+    }
+
+    let mut slider_bits = 0u32;
+    let mut values = Vec::new();
+    for (bit, slider) in sliders().enumerate() {
+        let Some(FeatureType::Slider(value)) = value_of(slider.name) else {
+            continue;
+        };
+        if is_eq_slider(slider) {
+            let tenths = (value.clamp(-12.0, 12.0) * 10.0).round() as i8;
+            if tenths != 0 {
+                slider_bits |= 1 << bit;
+                values.push(tenths as u8);
+            }
+        } else {
+            let hundredths =
+                (value * 100.0).round().clamp(0.0, u16::MAX as f32) as u16;
+            if hundredths != 0 {
+                slider_bits |= 1 << bit;
+                values.extend_from_slice(&hundredths.to_le_bytes());
+            }
+        }
+    }
+
+    let mut bytes = vec![SHARE_CODE_VERSION, layer_mask];
+    bytes.extend_from_slice(&toggle_bits.to_le_bytes());
+    bytes.extend_from_slice(&slider_bits.to_le_bytes());
+    bytes.extend_from_slice(&values);
+    let checksum = crc16(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+
+    format!("{}{}", SHARE_CODE_PREFIX, base64_url_encode(&bytes))
+}
+
+/// Decodes and validates a share code.
+/// Nothing that doesn't exist in [`FEATURES`] makes it through.
+pub fn decode(code: &str) -> Result<Profile, Box<dyn Error>> {
+    let code = code.trim();
+    let code = code.strip_prefix(SHARE_CODE_PREFIX).unwrap_or(code);
+    let bytes = base64_url_decode(code)
+        .ok_or_else(|| invalid("Share code contains invalid characters"))?;
+
+    if bytes.len() < 10 {
+        return Err(invalid("Share code is too short"));
+    }
+    if bytes[0] != SHARE_CODE_VERSION {
+        return Err(invalid(format!(
+            "Unsupported share code version {}",
+            bytes[0]
+        )));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 2);
+    if crc16(body).to_le_bytes() != checksum {
+        return Err(invalid("Share code checksum mismatch"));
+    }
+
+    let layer_mask = body[1];
+    let toggle_bits = u16::from_le_bytes([body[2], body[3]]);
+    let slider_bits = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+    let mut values = &body[8..];
+
+    if layer_mask >> SHARED_LAYERS.len() != 0 {
+        return Err(invalid("Share code contains unknown layers"));
+    }
+    if toggle_bits >> toggles().count() != 0
+        || slider_bits >> sliders().count() != 0
+    {
+        return Err(invalid("Share code contains unknown features"));
+    }
+
+    let mut features: Vec<Feature> = Vec::new();
+    for (bit, toggle) in toggles().enumerate() {
+        if toggle_bits & (1 << bit) != 0 {
+            let mut feature = toggle.clone();
+            feature.value = FeatureType::Toggle(true);
+            features.push(feature);
+        }
+    }
+    for (bit, slider) in sliders().enumerate() {
+        if slider_bits & (1 << bit) == 0 {
+            continue;
+        }
+        let value = if is_eq_slider(slider) {
+            let [tenths, rest @ ..] = values else {
+                return Err(invalid("Share code is truncated"));
+            };
+            values = rest;
+            let db = *tenths as i8 as f32 / 10.0;
+            if !(-12.0..=12.0).contains(&db) {
+                return Err(invalid(format!(
+                    "{} is out of range: {} dB",
+                    slider.name, db
+                )));
+            }
+            db
+        } else {
+            let [low, high, rest @ ..] = values else {
+                return Err(invalid("Share code is truncated"));
+            };
+            values = rest;
+            u16::from_le_bytes([*low, *high]) as f32 / 100.0
+        };
+        let mut feature = slider.clone();
+        feature.value = FeatureType::Slider(value);
+        features.push(feature);
+    }
+    if !values.is_empty() {
+        return Err(invalid("Share code has trailing data"));
+    }
+
+    let mut profile = Profile::from_features(&features);
+    let layers: Vec<Layer> = SHARED_LAYERS
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| layer_mask & (1 << bit) != 0)
+        .map(|(_, layer)| *layer)
+        .collect();
+    if features.iter().any(|f| !layers.contains(&Layer::of(f))) {
+        return Err(invalid("Share code sets features outside of its layers"));
+    }
+    profile.retain_layers(&layers);
+
+    Ok(profile)
+}
+
+/// CRC-16/CCITT-FALSE
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn base64_url_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        // 2 chars for 1 byte, 3 for 2, 4 for 3
+        for i in 0..=chunk.len() {
+            out.push(BASE64_URL[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+fn base64_url_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let value = BASE64_URL.iter().position(|&b| b == c)? as u32;
+        buffer = buffer << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(name: &str, value: FeatureType) -> Feature {
+        let mut feature =
+            FEATURES.iter().find(|f| f.name == name).unwrap().clone();
+        feature.value = value;
+        feature
+    }
+
+    #[test]
+    fn round_trip() {
+        let profile = Profile {
+            effects: Some(vec![
+                feature("SBX", FeatureType::Toggle(true)),
+                feature("Surround", FeatureType::Toggle(true)),
+                feature("Surround Slider", FeatureType::Slider(0.67)),
+            ]),
+            eq: Some(vec![
+                feature("Equalizer", FeatureType::Toggle(true)),
+                feature("EQ Pre-Amp", FeatureType::Slider(-4.5)),
+                feature("EQ 31Hz", FeatureType::Slider(3.2)),
+                feature("EQ 16kHz", FeatureType::Slider(-12.0)),
+            ]),
+            ..Default::default()
+        };
+
+        let code = encode(&profile);
+        assert!(code.starts_with(SHARE_CODE_PREFIX));
+        let decoded = decode(&code).unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&profile).unwrap()
+        );
+        assert_eq!(decoded.layers(), [Layer::Effects, Layer::Eq]);
+        assert_eq!(encode(&decoded), code);
+    }
+
+    #[test]
+    fn empty_layer_round_trip() {
+        let profile = Profile {
+            routing: Some(Vec::new()),
+            ..Default::default()
+        };
+        let decoded = decode(&encode(&profile)).unwrap();
+        assert_eq!(decoded.layers(), [Layer::Routing]);
+        assert_eq!(decoded.routing.unwrap().len(), 0);
+    }
+
+    #[test]
+    fn rejects_corrupted_codes() {
+        let code = encode(&Profile::from_ten_band_eq([1.0; 11]));
+        let mut corrupted = code.clone().into_bytes();
+        let last = corrupted.len() - 3;
+        corrupted[last] = if corrupted[last] == b'A' { b'B' } else { b'A' };
+        let corrupted = String::from_utf8(corrupted).unwrap();
+        assert!(decode(&corrupted).is_err());
+        assert!(decode("G6-not*base64").is_err());
+        assert!(decode(&code[..code.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+    }
+
+    #[test]
+    fn base64_url_round_trip() {
+        for len in 0..8 {
+            let bytes: Vec<u8> =
+                (0..len).map(|i: u8| i.wrapping_mul(37) ^ 0xf0).collect();
+            let encoded = base64_url_encode(&bytes);
+            assert_eq!(base64_url_decode(&encoded).unwrap(), bytes);
+        }
+        assert_eq!(base64_url_encode(b"\xfb\xff"), "-_8");
+    }
+}
@@ -0,0 +1,207 @@
+//! EQ from a headphone measurement and a target curve
+//!
+//! Measurements and targets are CSVs of frequency and dB,
+//! e.g. as exported by REW or AutoEq:
+//! ```text
+//! frequency,raw
+//! 20,-4.2
+//! 20.2,-4.1
+//! ```
+
+use serde::Serialize;
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use utoipa::ToSchema;
+
+use crate::eq;
+use crate::fit::{self, FitResult};
+use crate::profile::Profile;
+
+/// Target used if none is given
+pub const DEFAULT_TARGET: &str = "harman_over_ear_2018";
+
+/// Bundled targets, by name
+const TARGETS: &[(&str, &str)] = &[
+    (
+        "harman_over_ear_2018",
+        include_str!("../data/targets/harman_over_ear_2018.csv"),
+    ),
+    (
+        "harman_in_ear_2019",
+        include_str!("../data/targets/harman_in_ear_2019.csv"),
+    ),
+];
+
+/// Range the measurement is levelled to the target over,
+/// so the EQ corrects the shape and not the loudness
+pub const NORMALIZE_FROM: f64 = 200.0;
+pub const NORMALIZE_TO: f64 = 2000.0;
+
+/// A frequency response, sorted by frequency
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Curve {
+    pub points: Vec<(f64, f64)>,
+}
+
+/// The measurement corrected towards the target with the G6 bands
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct TargetEq {
+    /// The residual is between the bands and the needed correction
+    pub fit: FitResult,
+    /// EQ layer with the fitted bands
+    pub profile: Profile,
+}
+
+fn invalid_data(message: String) -> Box<dyn Error> {
+    Box::new(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+impl Curve {
+    /// Parses frequency / dB pairs separated by `,`, `;`, tabs or spaces.
+    /// Further columns, `#` and `*` comments (REW) and a header line
+    /// are skipped.
+    pub fn parse(content: &str) -> Result<Self, Box<dyn Error>> {
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        let mut header_seen = false;
+
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', '*']) {
+                continue;
+            }
+            let mut values = line
+                .split([',', ';', '\t', ' '])
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<f64>());
+            match (values.next(), values.next()) {
+                (Some(Ok(freq)), Some(Ok(gain))) => {
+                    if !(freq > 0.0 && freq.is_finite() && gain.is_finite()) {
+                        return Err(invalid_data(format!(
+                            "Line {}: invalid point {:?}",
+                            idx + 1,
+                            line
+                        )));
+                    }
+                    points.push((freq, gain));
+                }
+                _ if !header_seen && points.is_empty() => header_seen = true,
+                _ => {
+                    return Err(invalid_data(format!(
+                        "Line {}: expected frequency and dB, got {:?}",
+                        idx + 1,
+                        line
+                    )));
+                }
+            }
+        }
+
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points.dedup_by(|a, b| a.0 == b.0);
+        if points.len() < 2 {
+            return Err(invalid_data(
+                "A curve needs at least 2 points".to_string(),
+            ));
+        }
+        Ok(Self { points })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+            .map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))
+    }
+
+    /// A bundled target, see [`builtin_targets`]
+    pub fn builtin(name: &str) -> Option<Self> {
+        TARGETS
+            .iter()
+            .find(|(target, _)| *target == name)
+            .and_then(|(_, content)| Self::parse(content).ok())
+    }
+
+    /// dB at `freq`, see [`eq::interpolate`]
+    pub fn at(&self, freq: f64) -> f64 {
+        eq::interpolate(&self.points, freq)
+    }
+}
+
+/// Names of the bundled targets
+pub fn builtin_targets() -> Vec<&'static str> {
+    TARGETS.iter().map(|(name, _)| *name).collect()
+}
+
+/// A bundled target by name, or a target CSV file.
+/// For the command line, the API only takes bundled targets.
+pub fn load_target(target: &str) -> Result<Curve, Box<dyn Error>> {
+    match Curve::builtin(target) {
+        Some(curve) => Ok(curve),
+        None if Path::new(target).is_file() => Curve::load(Path::new(target)),
+        None => Err(Box::new(std::io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "Unknown target {}, expected a file or one of {}",
+                target,
+                builtin_targets().join(", ")
+            ),
+        ))),
+    }
+}
+
+/// Fits the bands to the difference between target and measurement.
+/// Both are levelled over [`NORMALIZE_FROM`]..[`NORMALIZE_TO`] first.
+pub fn target_eq(measurement: &Curve, target: &Curve) -> TargetEq {
+    let error = |freq: f64| target.at(freq) - measurement.at(freq);
+    let normalize_points =
+        eq::log_spaced(fit::FIT_POINTS, NORMALIZE_FROM, NORMALIZE_TO);
+    let offset = normalize_points.iter().map(|f| error(*f)).sum::<f64>()
+        / normalize_points.len() as f64;
+
+    let fit = fit::fit_curve(|freq| error(freq) - offset);
+    TargetEq {
+        profile: Profile::from_ten_band_eq(fit.bands),
+        fit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rew_export() {
+        let rew = "\
+* Measurement data measured by REW V5.20.13
+* Source: Umik-1  Gain: 18dB  , R channel
+* Format: 1/48 octave smoothing
+* Dated: 12 Mar 2024 21:14:03
+* REW Settings:
+*  C-weighting compensation: Off
+*  Target level: 75.0 dB
+* Note:Delay 0,0000 ms (0,00 m, 0,00 ft) using estimated IR delay
+* Measurement: HD650 L
+* Smoothing: 1/48 octave
+* Frequency Step: 1/48 octave
+* Start Frequency: 20,00 Hz
+*
+* Freq(Hz) SPL(dB) Phase(degrees)
+20.000000 72.345 -45.6
+20.290000 72.401 -44.9
+20.580000 72.460 -44.1
+";
+        let curve = Curve::parse(rew).unwrap();
+        assert_eq!(curve.points.len(), 3);
+        assert_eq!(curve.points[0], (20.0, 72.345));
+    }
+
+    #[test]
+    fn parses_csv_with_header() {
+        let curve =
+            Curve::parse("frequency,raw\n20,-4.2\n20.2,-4.1\n").unwrap();
+        assert_eq!(curve.points, vec![(20.0, -4.2), (20.2, -4.1)]);
+    }
+
+    #[test]
+    fn rejects_a_second_header() {
+        assert!(Curve::parse("frequency,raw\nfoo,bar\n20,1\n30,2\n").is_err());
+    }
+}
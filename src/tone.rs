@@ -0,0 +1,173 @@
+//! Tone controls: bass, treble, tilt and V-shape macros that drive the
+//! 10 bands, on top of an optional base profile.
+//! Profiles store the macro values, the bands are computed when applied.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use utoipa::ToSchema;
+
+use crate::eq::{self, MAX_FREQ, MAX_GAIN, MIN_FREQ};
+use crate::fit::{self, Filter};
+use crate::presets;
+use crate::profile::Profile;
+
+/// Corner of the bass shelf
+pub const BASS_FREQ: f64 = 105.0;
+/// Corner of the treble shelf
+pub const TREBLE_FREQ: f64 = 6000.0;
+/// Tilt and V-shape pivot around this frequency
+pub const PIVOT_FREQ: f64 = 1000.0;
+/// Steepest tilt in dB per octave
+pub const MAX_TILT: f32 = 2.0;
+
+/// Q of the bass and treble shelves
+const SHELF_Q: f64 = 0.71;
+
+/// Octaves from the pivot at which the V-shape reaches its full amount
+const V_SHAPE_OCTAVES: f64 = 5.0;
+
+/// How deep base profiles with tone controls may be nested
+const MAX_DEPTH: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToneControls {
+    /// Low shelf gain in dB
+    pub bass: f32,
+    /// High shelf gain in dB
+    pub treble: f32,
+    /// dB per octave around 1 kHz, positive is brighter
+    pub tilt: f32,
+    /// Boost of lows and highs in dB, rising from 0 at 1 kHz
+    pub v_shape: f32,
+    /// Profile whose EQ the macros go on top of, flat if `None`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+}
+
+impl ToneControls {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for (name, value, max) in [
+            ("Bass", self.bass, MAX_GAIN),
+            ("Treble", self.treble, MAX_GAIN),
+            ("Tilt", self.tilt, MAX_TILT),
+            ("V-shape", self.v_shape, MAX_GAIN),
+        ] {
+            if !(-max..=max).contains(&value) {
+                return Err(Box::new(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} must be within ±{}", name, max),
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Response in dB of the macros at `freq`
+    pub fn response(&self, freq: f64) -> f64 {
+        let octaves = (freq / PIVOT_FREQ).log2();
+        let shelves = [
+            Filter::LowShelf {
+                freq: BASS_FREQ,
+                gain: self.bass as f64,
+                q: SHELF_Q,
+            },
+            Filter::HighShelf {
+                freq: TREBLE_FREQ,
+                gain: self.treble as f64,
+                q: SHELF_Q,
+            },
+        ];
+
+        fit::filters_response(&shelves, freq)
+            + self.tilt as f64 * octaves
+            + self.v_shape as f64 * (octaves.abs() / V_SHAPE_OCTAVES).min(1.0)
+    }
+
+    /// Band gains that approximate the macros
+    pub fn gains(&self) -> [f32; 10] {
+        let fit = fit::fit_curve(|freq| self.response(freq));
+        let mut gains = [0.0; 10];
+        gains.copy_from_slice(&fit.bands[1..]);
+        gains
+    }
+
+    /// Pre-Amp and bands with the macros on top of `base`,
+    /// both in the layout of [`crate::BlasterXG6::get_ten_band_eq`].
+    /// The Pre-Amp takes the peak of the result down to 0 dB,
+    /// or stays lower if the base has it lower.
+    pub fn ten_band_eq(&self, base: &[f32; 11]) -> [f32; 11] {
+        let mut bands = *base;
+        for (band, gain) in bands[1..].iter_mut().zip(self.gains()) {
+            *band = ((*band + gain) * 10.0).round() / 10.0;
+            *band = band.clamp(-MAX_GAIN, MAX_GAIN);
+        }
+
+        let peak = eq::log_spaced(fit::FIT_POINTS, MIN_FREQ, MAX_FREQ)
+            .into_iter()
+            .map(|freq| eq::bands_response(&bands[1..], freq))
+            .fold(0.0, f64::max);
+        let preamp =
+            ((-peak).max(-MAX_GAIN as f64) * 10.0).floor() as f32 / 10.0;
+        bands[0] = base[0].min(preamp);
+        bands
+    }
+}
+
+/// Pre-Amp and bands of a named profile, its tone controls resolved
+pub fn profile_bands(
+    profile_dir: &Path,
+    name: &str,
+) -> Result<[f32; 11], Box<dyn Error>> {
+    profile_bands_at(profile_dir, name, 0)
+}
+
+fn profile_bands_at(
+    profile_dir: &Path,
+    name: &str,
+    depth: usize,
+) -> Result<[f32; 11], Box<dyn Error>> {
+    if depth >= MAX_DEPTH {
+        return Err(Box::new(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Base profiles nest too deep at {}", name),
+        )));
+    }
+    let mut profile = presets::load_named(profile_dir, name).map_err(|e| {
+        std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Base profile {}: {}", name, e),
+        )
+    })?;
+    resolve_at(&mut profile, profile_dir, depth + 1)?;
+    Ok(profile.ten_band_eq().unwrap_or([0.0; 11]))
+}
+
+/// Fills the EQ layer of a profile from its tone controls.
+/// Profiles without tone controls are left as they are.
+pub fn resolve(
+    profile: &mut Profile,
+    profile_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    resolve_at(profile, profile_dir, 0)
+}
+
+fn resolve_at(
+    profile: &mut Profile,
+    profile_dir: &Path,
+    depth: usize,
+) -> Result<(), Box<dyn Error>> {
+    let Some(tone) = &profile.tone else {
+        return Ok(());
+    };
+    tone.validate()?;
+
+    let base = match &tone.base {
+        Some(name) => profile_bands_at(profile_dir, name, depth)?,
+        None => [0.0; 11],
+    };
+    profile.eq = Profile::from_ten_band_eq(tone.ten_band_eq(&base)).eq;
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::api::AppState;
+use crate::events::Event;
+use crate::is_own_write;
+use crate::profile::Profile;
+
+/// Editors and git write files in several steps,
+/// changes are collected until things stay quiet for this long
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Only `name.json` files are profiles,
+/// hidden temp files and `.bak` backups are not
+fn profile_name(path: &Path) -> Option<String> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    let name = path.file_stem()?.to_string_lossy().to_string();
+    (!name.starts_with('.')).then_some(name)
+}
+
+/// Validates every profile in the directory and records the broken ones
+pub async fn scan_profiles(state: &AppState, dir: &Path) {
+    let paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+
+    for path in paths {
+        let Some(name) = profile_name(&path) else {
+            continue;
+        };
+        if let Err(e) = Profile::load(&path).map_err(|e| e.to_string()) {
+            error!("Profile {} is invalid: {}", path.display(), e);
+            state.profile_errors.lock().await.insert(name, e);
+        }
+    }
+}
+
+/// Watches the profile directory for changes made by other programs.
+/// Changed profiles are validated, and re-applied if they're active.
+pub fn spawn_profile_watcher(
+    state: Arc<AppState>,
+    dir: PathBuf,
+) -> Result<tokio::task::JoinHandle<()>, Box<dyn Error>> {
+    std::fs::create_dir_all(&dir)?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<PathBuf>();
+    let mut watcher = notify::recommended_watcher(
+        move |result: notify::Result<notify::Event>| match result {
+            Ok(event) => {
+                if matches!(
+                    event.kind,
+                    EventKind::Create(_)
+                        | EventKind::Modify(_)
+                        | EventKind::Remove(_)
+                ) {
+                    for path in event.paths {
+                        let _ = tx.send(path);
+                    }
+                }
+            }
+            Err(e) => warn!("Profile watcher error: {}", e),
+        },
+    )?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    Ok(tokio::spawn(async move {
+        // dropping the watcher stops it, it has to live as long as the task
+        let _watcher = watcher;
+
+        scan_profiles(&state, &dir).await;
+
+        while let Some(first) = rx.recv().await {
+            let mut changed = BTreeSet::from([first]);
+            while let Ok(Some(path)) =
+                tokio::time::timeout(WATCH_DEBOUNCE, rx.recv()).await
+            {
+                changed.insert(path);
+            }
+
+            for path in changed {
+                if let Some(name) = profile_name(&path) {
+                    handle_change(&state, &path, &name).await;
+                }
+            }
+        }
+    }))
+}
+
+async fn handle_change(state: &AppState, path: &Path, name: &str) {
+    let Ok(content) = std::fs::read(path) else {
+        info!("Profile {} was removed", name);
+        state.profile_errors.lock().await.remove(name);
+        state.events.publish(Event::ProfileFile {
+            name: name.to_string(),
+            removed: true,
+            error: None,
+        });
+        return;
+    };
+
+    if is_own_write(path, &content) {
+        return;
+    }
+
+    let profile = match std::str::from_utf8(&content)
+        .map_err(|e| e.to_string())
+        .and_then(|content| Profile::parse(content).map_err(|e| e.to_string()))
+    {
+        Ok(profile) => profile,
+        Err(e) => {
+            error!("Profile {} is invalid: {}", path.display(), e);
+            state
+                .profile_errors
+                .lock()
+                .await
+                .insert(name.to_string(), e.clone());
+            state.events.publish(Event::ProfileFile {
+                name: name.to_string(),
+                removed: false,
+                error: Some(e),
+            });
+            return;
+        }
+    };
+    state.profile_errors.lock().await.remove(name);
+    info!("Profile {} changed on disk", name);
+    state.events.publish(Event::ProfileFile {
+        name: name.to_string(),
+        removed: false,
+        error: None,
+    });
+
+    let mut device = state.device.lock().await;
+
+    // only the layers that came from this profile get re-applied
+    let active: Vec<_> = device
+        .active_layers
+        .iter()
+        .filter(|(_, active)| active.profile == name)
+        .map(|(layer, _)| *layer)
+        .collect();
+    if active.is_empty() {
+        return;
+    }
+
+    let mut reload = profile;
+    reload.retain_layers(&active);
+
+    let before = device.features.clone();
+    let result = device
+        .apply_layered_profile(name, &reload)
+        .map_err(|e| e.to_string());
+    state.history.lock().await.record(
+        format!("Reload profile {}", name),
+        before,
+        device.features.clone(),
+    );
+    // no save, a change on disk shouldn't cause a write in turn
+
+    match result {
+        Ok(()) => info!("Re-applied profile {}", name),
+        Err(e) => error!("Failed to re-apply profile {}: {}", name, e),
+    }
+}